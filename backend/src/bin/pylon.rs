@@ -0,0 +1,403 @@
+//! A command-line client for sending and receiving payloads through encrypted wormhole tunnels.
+//!
+//! The client talks to the rendezvous server directly (without going through the web service), and
+//! uses the same application ID as the web service by default, so it can exchange messages and
+//! files with web users.
+//!
+//! Exit codes:
+//!
+//! * `0` - Success.
+//! * `1` - Any other failure.
+//! * `2` - Invalid usage or configuration.
+//! * `3` - Invalid or unknown wormhole code.
+//! * `4` - Timed out (waiting for the peer, or a wormhole operation).
+//! * `5` - The rendezvous server could not be reached.
+//! * `6` - The received payload is invalid (or in an unsupported schema version), or failed its
+//!   integrity check.
+//! * `7` - The message could not be encrypted or decrypted (eg: because of a wrong passphrase).
+//! * `8` - The transfer was cancelled.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use clap::{Args, Parser, Subcommand};
+
+use rocket::tokio::fs::{self, File, OpenOptions};
+use rocket::tokio::time::timeout;
+
+use serde_json::{json, Value};
+
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+use pylon_web::config::{ConfigError, PylonConfig};
+use pylon_web::controllers::file_digest;
+use pylon_web::core::{Mode, Payload, Pylon, PylonError, Received, TimeoutStage};
+use pylon_web::crypto;
+
+/// Sends and receives messages and files through encrypted wormhole tunnels.
+#[derive(Parser)]
+#[command(
+    name = "pylon",
+    version,
+    after_help = "Exit codes: 0 success, 1 other failure, 2 invalid usage or configuration, \
+                  3 invalid or unknown code, 4 timeout, 5 rendezvous server unreachable, \
+                  6 invalid payload or integrity failure, 7 encryption error, 8 cancelled."
+)]
+struct Cli {
+    /// Print machine-readable JSON (one object per line) instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(flatten)]
+    server: ServerArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// The rendezvous settings, which must match the peer's for the peers to find each other.
+#[derive(Args)]
+struct ServerArgs {
+    /// The WebSocket URL of the rendezvous server.
+    #[arg(long, global = true, env = "PYLON_RENDEZVOUS_URL")]
+    rendezvous_url: Option<String>,
+
+    /// The wormhole application ID.
+    #[arg(long, global = true, env = "PYLON_APP_ID")]
+    app_id: Option<String>,
+
+    /// The URL of the transit relay server, used for file transfers.
+    #[arg(long, global = true, env = "PYLON_TRANSIT_RELAY_URL")]
+    transit_relay_url: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sends a message (given as an argument or on stdin) or a file, and prints its code.
+    Send {
+        /// The message to send (read from stdin if neither a message nor a file is given).
+        #[arg(conflicts_with = "file")]
+        message: Option<String>,
+
+        /// The file to send.
+        #[arg(long, short)]
+        file: Option<PathBuf>,
+
+        /// The number of words in the generated code.
+        #[arg(long, short)]
+        words: Option<usize>,
+
+        /// Encrypt the message end-to-end with this passphrase.
+        #[arg(long, env = "PYLON_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+    },
+
+    /// Receives the message or file sent with a code.
+    Receive {
+        /// The wormhole code.
+        code: String,
+
+        /// Where to write a received file (defaults to the sender's file name, in the current
+        /// directory).
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
+        /// Decrypt an end-to-end encrypted message with this passphrase.
+        #[arg(long, env = "PYLON_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+    },
+}
+
+/// Prints the progress and results of a command, as text or JSON.
+struct Output {
+    /// Whether to print JSON.
+    json: bool,
+}
+
+impl Output {
+    /// Prints an event.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to print in text mode.
+    /// * `value` - The JSON object to print in JSON mode.
+    fn print(&self, text: &str, value: Value) {
+        if self.json {
+            println!("{}", value);
+        } else {
+            println!("{}", text);
+        }
+    }
+
+    /// Prints an error (to stdout in JSON mode, so that scripts find it with the other events).
+    ///
+    /// # Arguments
+    ///
+    /// * `error_code` - The machine-readable identifier of the error.
+    /// * `message` - The error message.
+    fn error(&self, error_code: &str, message: &str) {
+        if self.json {
+            println!(
+                "{}",
+                json!({"event": "error", "error": error_code, "message": message})
+            );
+        } else {
+            eprintln!("Error: {}", message);
+        }
+    }
+}
+
+/// Maps an error to the exit code of the process.
+///
+/// # Arguments
+///
+/// * `e` - The error.
+fn exit_code(e: &PylonError) -> u8 {
+    match e {
+        PylonError::EmptyPayload(_) | PylonError::InvalidCodeLength(_) => 2,
+        PylonError::UnknownCode | PylonError::BadCode => 3,
+        PylonError::Timeout(_) => 4,
+        PylonError::RendezvousUnreachable(_) => 5,
+        PylonError::InvalidPayload(_)
+        | PylonError::PayloadTooLarge
+        | PylonError::Integrity(_)
+        | PylonError::UnsupportedSchemaVersion(_) => 6,
+        PylonError::Crypto(_) => 7,
+        PylonError::Cancelled => 8,
+        PylonError::RateLimited(_) | PylonError::Aborted | PylonError::Internal(_) => 1,
+    }
+}
+
+/// Builds the client configuration from the defaults and the command-line arguments.
+///
+/// # Arguments
+///
+/// * `args` - The rendezvous settings.
+fn config(args: ServerArgs) -> Result<PylonConfig, ConfigError> {
+    let defaults = PylonConfig::default();
+    let config = PylonConfig {
+        rendezvous_url: args.rendezvous_url.unwrap_or(defaults.rendezvous_url),
+        app_id: args.app_id.unwrap_or(defaults.app_id),
+        transit_relay_url: args.transit_relay_url.unwrap_or(defaults.transit_relay_url),
+        ..PylonConfig::default()
+    };
+
+    config.validate()?;
+
+    Ok(config)
+}
+
+/// Sends a message or a file, printing the code as soon as it's generated.
+///
+/// # Arguments
+///
+/// * `message` - The message to send.
+/// * `file` - The file to send (instead of a message).
+/// * `words` - The number of words in the generated code.
+/// * `passphrase` - The passphrase to encrypt the message with.
+/// * `config` - The client configuration.
+/// * `out` - The output.
+async fn send(
+    message: Option<String>,
+    file: Option<PathBuf>,
+    words: Option<usize>,
+    passphrase: Option<String>,
+    config: &PylonConfig,
+    out: &Output,
+) -> Result<(), PylonError> {
+    let words = words.unwrap_or(config.code_length);
+
+    if words < config.min_code_length || words > config.max_code_length {
+        return Err(PylonError::InvalidCodeLength(format!(
+            "codes must have between {} and {} words",
+            config.min_code_length, config.max_code_length
+        )));
+    }
+
+    // Read (and hash) everything up front, so that the peer isn't kept waiting on us.
+    let (upload, message) = match (file, message) {
+        (Some(path), _) => {
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| PylonError::EmptyPayload("A file name is required".into()))?;
+            let (size, checksum) = file_digest(&mut File::open(&path).await?).await?;
+
+            (Some((path, file_name, size, checksum)), None)
+        }
+        (None, Some(message)) => (None, Some(message)),
+        (None, None) => {
+            let mut message = String::new();
+            io::stdin().read_to_string(&mut message)?;
+            (None, Some(message))
+        }
+    };
+
+    let mut pylon = Pylon::with_code_length(words, config).await?;
+    let code = pylon.code.clone().unwrap_or_default();
+    out.print(&code, json!({"event": "code", "code": code}));
+
+    // Like the service's senders, wait for a receiver no longer than the code remains valid.
+    let handshake_timeout = config
+        .timeouts
+        .handshake()
+        .min(Duration::from_secs(config.code_ttl));
+    timeout(handshake_timeout, pylon.connect())
+        .await
+        .map_err(|_| PylonError::Timeout(TimeoutStage::Handshake))??;
+
+    match (upload, message) {
+        (Some((path, file_name, size, checksum)), _) => {
+            let payload = Payload {
+                code: code.clone(),
+                time: Some(SystemTime::now()),
+                checksum: Some(checksum),
+                file_name: Some(file_name),
+                size: Some(size),
+                ..Default::default()
+            };
+
+            let mut file = File::open(&path).await?.compat();
+            pylon.send_file(&payload, &mut file, config).await?;
+        }
+        (None, message) => {
+            let mut payload = Payload::from((message.unwrap_or_default().as_str(), code.as_str()));
+
+            if payload.message.as_deref() == Some("") {
+                return Err(PylonError::EmptyPayload("Message cannot be empty".into()));
+            }
+
+            if let Some(passphrase) = &passphrase {
+                payload = crypto::encrypt(&payload, passphrase)?;
+            }
+
+            pylon.activate(Some(&payload)).await?;
+        }
+    }
+
+    out.print("Sent", json!({"event": "sent", "code": code}));
+
+    Ok(())
+}
+
+/// Receives the message or file sent with a code, and prints it.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code.
+/// * `output` - Where to write a received file.
+/// * `passphrase` - The passphrase to decrypt the message with.
+/// * `config` - The client configuration.
+/// * `out` - The output.
+async fn receive(
+    code: String,
+    output: Option<PathBuf>,
+    passphrase: Option<String>,
+    config: &PylonConfig,
+    out: &Output,
+) -> Result<(), PylonError> {
+    let pylon = Pylon::new(Mode::Receiver, Some(code), config).await?;
+
+    match pylon.receive(config).await? {
+        Received::Message(mut payload) => {
+            if payload.encryption.is_some() {
+                let passphrase = passphrase.ok_or_else(|| {
+                    PylonError::Crypto(
+                        "the message is encrypted, but no passphrase was given".into(),
+                    )
+                })?;
+                payload = crypto::decrypt(&payload, &passphrase)?;
+                payload.verify()?;
+            }
+
+            out.print(
+                payload.message.as_deref().unwrap_or_default(),
+                json!({"event": "received", "payload": payload}),
+            );
+        }
+        Received::File(offer) => {
+            let payload = offer.payload.clone();
+            let path = match output {
+                Some(path) => path,
+                None => Path::new(payload.file_name.as_deref().unwrap_or_default())
+                    .file_name()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| {
+                        PylonError::InvalidPayload("the sender's file name is invalid".into())
+                    })?,
+            };
+
+            // Never overwrite an existing file.
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await?;
+
+            let res = async {
+                offer.accept(&mut file.compat_write()).await?;
+
+                let (size, checksum) = file_digest(&mut File::open(&path).await?).await?;
+
+                if Some(size) != payload.size || Some(&checksum) != payload.checksum.as_ref() {
+                    return Err(PylonError::Integrity(
+                        "the received file does not match the sender's checksum".into(),
+                    ));
+                }
+
+                Ok(())
+            }
+            .await;
+
+            if let Err(e) = res {
+                let _ = fs::remove_file(&path).await;
+                return Err(e);
+            }
+
+            out.print(
+                &path.display().to_string(),
+                json!({"event": "received", "payload": payload, "path": path}),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[rocket::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let out = Output { json: cli.json };
+
+    let config = match config(cli.server) {
+        Ok(config) => config,
+        Err(e) => {
+            out.error("invalid_config", &format!("Invalid configuration: {}", e));
+            return ExitCode::from(2);
+        }
+    };
+
+    let res = match cli.command {
+        Command::Send {
+            message,
+            file,
+            words,
+            passphrase,
+        } => send(message, file, words, passphrase, &config, &out).await,
+        Command::Receive {
+            code,
+            output,
+            passphrase,
+        } => receive(code, output, passphrase, &config, &out).await,
+    };
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            out.error(e.error_code(), &e.to_string());
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
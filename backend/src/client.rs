@@ -0,0 +1,371 @@
+//! An async client for the REST API.
+//!
+//! Only available with the `client` feature.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use rocket::http::{ContentType, Method, Status};
+use rocket::local::asynchronous::Client;
+use rocket::tokio::time::sleep;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use url::Url;
+
+use crate::consts::{API_PREFIX, CLIENT_MAX_RETRIES, CLIENT_RETRY_BACKOFF};
+use crate::core::{CodeInfo, Payload, TimeoutStage};
+use crate::Response;
+
+/// An error response of the service.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    /// The HTTP status code.
+    pub status: u16,
+
+    /// The stable, machine-readable error identifier (see [`crate::core::PylonError::error_code`]),
+    /// if the response was sent by the service itself.
+    pub error_code: Option<String>,
+
+    /// The error message.
+    pub message: Option<String>,
+}
+
+/// A custom error type for client errors.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The service responded with an error.
+    Api(ApiError),
+
+    /// The request could not be sent, or the response could not be read.
+    Transport(String),
+
+    /// The response is not a valid response envelope.
+    Decode(String),
+}
+
+impl ClientError {
+    /// Returns the machine-readable identifier of the service error, if any.
+    pub fn error_code(&self) -> Option<&str> {
+        match self {
+            Self::Api(e) => e.error_code.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Api(e) => match &e.message {
+                Some(message) => write!(f, "Service error ({}): {}", e.status, message),
+                None => write!(f, "Service error ({})", e.status),
+            },
+            Self::Transport(e) => write!(f, "Request failed: {}", e),
+            Self::Decode(e) => write!(f, "Invalid response: {}", e),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+/// Sends requests to the service.
+#[rocket::async_trait]
+pub trait Transport: Send + Sync {
+    /// Sends a request, and returns the status and body of the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The request method.
+    /// * `path` - The request path (and query), relative to the service root.
+    /// * `body` - The JSON request body, if any.
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<(Status, String), ClientError>;
+}
+
+/// Sends requests to a service over HTTP(S).
+pub struct HttpTransport {
+    /// The root URL of the service.
+    base_url: Url,
+
+    /// The underlying HTTP client.
+    client: reqwest::Client,
+}
+
+impl HttpTransport {
+    /// Creates a new HttpTransport.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The root URL of the service.
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        let mut base_url =
+            Url::parse(base_url).map_err(|e| ClientError::Transport(format!("bad URL: {}", e)))?;
+
+        // Request paths are joined to the base URL, which would otherwise replace its last segment.
+        if !base_url.path().ends_with('/') {
+            let path = format!("{}/", base_url.path());
+            base_url.set_path(&path);
+        }
+
+        Ok(Self {
+            base_url,
+            client: reqwest::Client::new(),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Transport for HttpTransport {
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<(Status, String), ClientError> {
+        let url = self
+            .base_url
+            .join(path.trim_start_matches('/'))
+            .map_err(|e| ClientError::Transport(format!("bad URL: {}", e)))?;
+        let method = reqwest::Method::from_bytes(method.as_str().as_bytes())
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+
+        let mut request = self.client.request(method, url);
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+        let status = Status::new(response.status().as_u16());
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+
+        Ok((status, body))
+    }
+}
+
+/// Dispatches requests to a local Rocket instance, eg: in tests.
+#[rocket::async_trait]
+impl Transport for Client {
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<(Status, String), ClientError> {
+        let mut request = self.req(method, path.to_string());
+        if let Some(body) = body {
+            request = request.header(ContentType::JSON).body(body);
+        }
+
+        let response = request.dispatch().await;
+        let status = response.status();
+        let body = response.into_string().await.unwrap_or_default();
+
+        Ok((status, body))
+    }
+}
+
+/// An async client for the `/code`, `/send` and `/receive` routes.
+///
+/// Requests that fail with a transient server error (`502`, `503` or `504`) are retried with
+/// exponential backoff. Since a failed `/send` may already have used up its code, `/send` requests
+/// are only retried on `503 Service Unavailable`, which means the request never reached a sender.
+/// Likewise, `/receive` requests are only retried on timeouts (`504 Gateway Timeout` or
+/// `408 Request Timeout`) if the receiver never completed its handshake with the sender.
+pub struct PylonClient<T: Transport = HttpTransport> {
+    /// The transport requests are sent with.
+    transport: T,
+
+    /// The maximum number of retries of a request.
+    max_retries: u32,
+
+    /// The delay before the first retry, doubled for each subsequent retry.
+    backoff: Duration,
+}
+
+impl PylonClient {
+    /// Creates a client for a service reachable over HTTP(S).
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - The root URL of the service.
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        Ok(Self::with_transport(HttpTransport::new(base_url)?))
+    }
+}
+
+impl<T: Transport> PylonClient<T> {
+    /// Creates a client that sends requests with a custom transport.
+    ///
+    /// # Arguments
+    ///
+    /// * `transport` - The transport.
+    pub fn with_transport(transport: T) -> Self {
+        Self {
+            transport,
+            max_retries: CLIENT_MAX_RETRIES,
+            backoff: Duration::from_millis(CLIENT_RETRY_BACKOFF),
+        }
+    }
+
+    /// Sets the retry policy for transient server errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_retries` - The maximum number of retries of a request (0 disables retries).
+    /// * `backoff` - The delay before the first retry, doubled for each subsequent retry.
+    pub fn retries(mut self, max_retries: u32, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Generates a wormhole code, for a pending sender held by the service.
+    ///
+    /// # Arguments
+    ///
+    /// * `words` - The number of words in the code (the service default if not given).
+    pub async fn code(&self, words: Option<usize>) -> Result<CodeInfo, ClientError> {
+        let path = match words {
+            Some(words) => format!("{}/code?words={}", API_PREFIX, words),
+            None => format!("{}/code", API_PREFIX),
+        };
+
+        self.call(Method::Get, &path, None, Retry::Gateway).await
+    }
+
+    /// Sends a payload through the pending sender of its code.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload to send.
+    pub async fn send(&self, payload: &Payload) -> Result<Payload, ClientError> {
+        let body =
+            serde_json::to_string(payload).map_err(|e| ClientError::Decode(e.to_string()))?;
+
+        let path = format!("{}/send", API_PREFIX);
+
+        self.call(Method::Post, &path, Some(body), Retry::Unavailable)
+            .await
+    }
+
+    /// Receives the payload sent with a code.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code.
+    pub async fn receive(&self, code: &str) -> Result<Payload, ClientError> {
+        let payload = Payload {
+            code: code.into(),
+            ..Default::default()
+        };
+        let body =
+            serde_json::to_string(&payload).map_err(|e| ClientError::Decode(e.to_string()))?;
+
+        let path = format!("{}/receive", API_PREFIX);
+
+        self.call(Method::Post, &path, Some(body), Retry::BeforeHandshake)
+            .await
+    }
+
+    /// Sends a request, retrying it on transient server errors, and unwraps the response envelope.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The request method.
+    /// * `path` - The request path (and query).
+    /// * `body` - The JSON request body, if any.
+    /// * `retry` - Which server errors the request can be safely repeated after.
+    async fn call<S: Serialize + DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+        retry: Retry,
+    ) -> Result<S, ClientError> {
+        let mut attempt = 0;
+
+        loop {
+            let (status, response) = self.transport.request(method, path, body.clone()).await?;
+            let transient = match (status.code, retry) {
+                (503, _) => true,
+                (502, Retry::Gateway | Retry::BeforeHandshake) => true,
+                (504, Retry::Gateway) => true,
+                (408 | 504, Retry::BeforeHandshake) => {
+                    let error_code = serde_json::from_str::<Response<()>>(&response)
+                        .ok()
+                        .and_then(|r| r.error_code);
+
+                    [TimeoutStage::Connect, TimeoutStage::Handshake]
+                        .iter()
+                        .any(|stage| error_code.as_deref() == Some(stage.error_code()))
+                }
+                _ => false,
+            };
+
+            if transient && attempt < self.max_retries {
+                sleep(self.backoff * 2u32.saturating_pow(attempt)).await;
+                attempt += 1;
+                continue;
+            }
+
+            return decode(status, &response);
+        }
+    }
+}
+
+/// The server errors a request can be safely repeated after, besides `503 Service Unavailable`.
+#[derive(Clone, Copy)]
+enum Retry {
+    /// None: the request may have had effects once it reached the service.
+    Unavailable,
+
+    /// Gateway errors (`502` and `504`).
+    Gateway,
+
+    /// Gateway errors and timeouts raised before the peers completed their handshake (`502`,
+    /// `504` for connect timeouts and `408` for handshake timeouts).
+    BeforeHandshake,
+}
+
+/// Unwraps the data of a response envelope, or turns an error response into an [`ApiError`].
+///
+/// # Arguments
+///
+/// * `status` - The response status.
+/// * `body` - The response body.
+fn decode<S: Serialize + DeserializeOwned>(status: Status, body: &str) -> Result<S, ClientError> {
+    if status.class().is_success() {
+        let response: Response<S> =
+            serde_json::from_str(body).map_err(|e| ClientError::Decode(e.to_string()))?;
+
+        return response
+            .data
+            .ok_or_else(|| ClientError::Decode("response has no data".into()));
+    }
+
+    // Errors returned by proxies in front of the service may not be wrapped in an envelope.
+    let response = serde_json::from_str::<Response<()>>(body).ok();
+
+    Err(ClientError::Api(ApiError {
+        status: status.code,
+        error_code: response.as_ref().and_then(|r| r.error_code.clone()),
+        message: response
+            .and_then(|r| r.message)
+            .or_else(|| status.reason().map(String::from)),
+    }))
+}
@@ -0,0 +1,822 @@
+//! Runtime configuration for the service.
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::Args;
+
+use magic_wormhole::rendezvous::DEFAULT_RENDEZVOUS_SERVER;
+use magic_wormhole::transit::DEFAULT_RELAY_SERVER;
+use magic_wormhole::{AppConfig, AppID};
+
+use rocket::data::ByteUnit;
+use rocket::figment::providers::{Env, Format, Serialized, Toml};
+use rocket::figment::{Figment, Profile};
+use rocket::http::uri::Absolute;
+use rocket::log::LogLevel;
+use rocket::Config;
+
+use serde::{Deserialize, Serialize};
+
+use url::Url;
+
+use crate::consts::{APP_ID, APP_VERSION, CODE_LENGTH, MAX_CODE_LENGTH, MIN_CODE_LENGTH};
+
+/// A custom error type for configuration errors.
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The application version information exchanged with the peer during the wormhole handshake.
+///
+/// This must serialize to a JSON object, since the file transfer protocol expects one.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AppVersion {
+    /// The pylon-web version.
+    pub version: &'static str,
+}
+
+/// The service configuration.
+///
+/// Extracted from Rocket's configuration sources at startup (see [`figment`]), so every value can be
+/// set through `Rocket.toml`, `Pylon.toml`, or a `ROCKET_`- or `PYLON_`-prefixed environment
+/// variable (eg: `PYLON_RENDEZVOUS_URL`).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PylonConfig {
+    /// The URL of the rendezvous (mailbox) server used to pair senders and receivers.
+    pub rendezvous_url: String,
+
+    /// The application ID that scopes wormholes on the rendezvous server.
+    ///
+    /// Only clients using the same application ID can talk to each other.
+    pub app_id: String,
+
+    /// The URL of the transit relay server used for file transfers when a direct connection between
+    /// the peers is not possible.
+    pub transit_relay_url: String,
+
+    /// The number of words in generated codes (the numeric nameplate excluded).
+    pub code_length: usize,
+
+    /// The minimum number of words clients may request codes with.
+    pub min_code_length: usize,
+
+    /// The maximum number of words clients may request codes with.
+    pub max_code_length: usize,
+
+    /// Time (in seconds) a generated code stays valid if no payload is sent through it.
+    pub code_ttl: u64,
+
+    /// Interval (in seconds) at which expired codes are evicted.
+    pub reap_interval: u64,
+
+    /// Time (in seconds) the readiness probe waits for the rendezvous server to respond.
+    pub readiness_timeout: u64,
+
+    /// The CORS policy.
+    pub cors: CorsConfig,
+
+    /// The security headers added to responses.
+    pub security_headers: SecurityHeadersConfig,
+
+    /// The per-client rate limits.
+    pub rate_limit: RateLimitConfig,
+
+    /// The deadlines of wormhole operations.
+    pub timeouts: TimeoutConfig,
+
+    /// The directory of the frontend's static files, served at `/` if set.
+    pub static_dir: Option<PathBuf>,
+}
+
+impl Default for PylonConfig {
+    fn default() -> Self {
+        Self {
+            rendezvous_url: DEFAULT_RENDEZVOUS_SERVER.into(),
+            app_id: APP_ID.into(),
+            transit_relay_url: DEFAULT_RELAY_SERVER.into(),
+            code_length: CODE_LENGTH,
+            min_code_length: MIN_CODE_LENGTH,
+            max_code_length: MAX_CODE_LENGTH,
+            code_ttl: 600,
+            reap_interval: 30,
+            readiness_timeout: 2,
+            cors: CorsConfig::default(),
+            security_headers: SecurityHeadersConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            timeouts: TimeoutConfig::default(),
+            static_dir: None,
+        }
+    }
+}
+
+impl PylonConfig {
+    /// Checks that the configured values are usable.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let url = Absolute::parse(&self.rendezvous_url).map_err(|e| {
+            ConfigError(format!(
+                "invalid rendezvous URL '{}': {}",
+                self.rendezvous_url, e
+            ))
+        })?;
+
+        if !matches!(url.scheme(), "ws" | "wss") {
+            return Err(ConfigError(format!(
+                "invalid rendezvous URL '{}': scheme must be 'ws' or 'wss'",
+                self.rendezvous_url
+            )));
+        }
+
+        if url.authority().is_none_or(|auth| auth.host().is_empty()) {
+            return Err(ConfigError(format!(
+                "invalid rendezvous URL '{}': missing host",
+                self.rendezvous_url
+            )));
+        }
+
+        if self.app_id.trim().is_empty() {
+            return Err(ConfigError("application ID cannot be empty".into()));
+        }
+
+        self.relay_url()?;
+
+        if self.min_code_length == 0 {
+            return Err(ConfigError(
+                "minimum code length must be at least 1 word".into(),
+            ));
+        }
+
+        if !(self.min_code_length..=self.max_code_length).contains(&self.code_length) {
+            return Err(ConfigError(format!(
+                "code length must be between {} and {} words, got {}",
+                self.min_code_length, self.max_code_length, self.code_length
+            )));
+        }
+
+        if self.code_ttl == 0 {
+            return Err(ConfigError("code TTL must be at least 1 second".into()));
+        }
+
+        if self.reap_interval == 0 {
+            return Err(ConfigError(
+                "reap interval must be at least 1 second".into(),
+            ));
+        }
+
+        if self.readiness_timeout == 0 {
+            return Err(ConfigError(
+                "readiness timeout must be at least 1 second".into(),
+            ));
+        }
+
+        self.cors.validate()?;
+        self.rate_limit.validate()?;
+        self.timeouts.validate()?;
+
+        if let Some(dir) = &self.static_dir {
+            if !dir.is_dir() {
+                return Err(ConfigError(format!(
+                    "static directory '{}' does not exist or is not a directory",
+                    dir.display()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the wormhole application config from the configured values.
+    pub fn app_config(&self) -> AppConfig<AppVersion> {
+        AppConfig {
+            id: AppID(Cow::from(self.app_id.clone())),
+            rendezvous_url: Cow::from(self.rendezvous_url.clone()),
+            app_version: AppVersion {
+                version: APP_VERSION,
+            },
+        }
+    }
+
+    /// Parses the configured transit relay URL.
+    pub fn relay_url(&self) -> Result<Url, ConfigError> {
+        let url = Url::parse(&self.transit_relay_url).map_err(|e| {
+            ConfigError(format!(
+                "invalid transit relay URL '{}': {}",
+                self.transit_relay_url, e
+            ))
+        })?;
+
+        if url.scheme() != "tcp" || url.host().is_none() || url.port().is_none() {
+            return Err(ConfigError(format!(
+                "invalid transit relay URL '{}': expected 'tcp://<host>:<port>'",
+                self.transit_relay_url
+            )));
+        }
+
+        Ok(url)
+    }
+}
+
+/// Configuration overrides given as command-line flags.
+///
+/// Unset flags leave the value from the other configuration sources in place (see [`figment`]).
+#[derive(Args, Serialize, Clone, Debug, Default)]
+pub struct ConfigArgs {
+    /// The configuration file to read instead of `Pylon.toml` (or `$PYLON_CONFIG`).
+    #[arg(long, value_name = "PATH")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+
+    /// The address to listen on (IPv4 or IPv6, eg: `0.0.0.0` or `::`).
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<IpAddr>,
+
+    /// The port to listen on.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    /// The URL of the rendezvous server.
+    #[arg(long, value_name = "URL")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendezvous_url: Option<String>,
+
+    /// The wormhole application ID.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>,
+
+    /// The URL of the transit relay server.
+    #[arg(long, value_name = "URL")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transit_relay_url: Option<String>,
+
+    /// The number of words in generated codes.
+    #[arg(long, value_name = "WORDS")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_length: Option<usize>,
+
+    /// Time (in seconds) a generated code stays valid.
+    #[arg(long, value_name = "SECS")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_ttl: Option<u64>,
+
+    /// Interval (in seconds) at which expired codes are evicted.
+    #[arg(long, value_name = "SECS")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reap_interval: Option<u64>,
+
+    /// The deadlines of wormhole operations.
+    #[command(flatten)]
+    pub timeouts: TimeoutArgs,
+
+    /// The per-client rate limits.
+    #[command(flatten)]
+    pub rate_limit: RateLimitArgs,
+
+    /// The maximum size of an uploaded file (eg: `100 MiB`).
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_unit)]
+    #[serde(skip)]
+    pub upload_limit: Option<ByteUnit>,
+
+    /// The directory of the frontend's static files.
+    #[arg(long, value_name = "PATH")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub static_dir: Option<PathBuf>,
+}
+
+/// Overrides of the `timeouts` table given as command-line flags (see [`TimeoutConfig`]).
+#[derive(Args, Serialize, Clone, Debug, Default)]
+pub struct TimeoutArgs {
+    /// Time (in seconds) allowed to connect to the rendezvous server.
+    #[arg(long, value_name = "SECS")]
+    #[serde(rename = "connect", skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+
+    /// Time (in seconds) allowed for the peer to complete the handshake.
+    #[arg(long, value_name = "SECS")]
+    #[serde(rename = "handshake", skip_serializing_if = "Option::is_none")]
+    pub handshake_timeout: Option<u64>,
+
+    /// Time (in seconds) allowed for the peer to send its payload.
+    #[arg(long, value_name = "SECS")]
+    #[serde(rename = "receive", skip_serializing_if = "Option::is_none")]
+    pub receive_timeout: Option<u64>,
+}
+
+/// Overrides of the `rate_limit` table given as command-line flags (see [`RateLimitConfig`]).
+#[derive(Args, Serialize, Clone, Debug, Default)]
+pub struct RateLimitArgs {
+    /// Whether requests are rate limited at all.
+    #[arg(long = "rate-limit", value_name = "BOOL")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    /// The header carrying the client IP address, set by a trusted reverse proxy.
+    #[arg(long, value_name = "HEADER")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trusted_proxy_header: Option<String>,
+
+    /// The budget for generating codes (eg: `10,30`).
+    #[arg(long, value_name = "BURST,PER_MINUTE", value_parser = parse_rate_budget)]
+    #[serde(rename = "code", skip_serializing_if = "Option::is_none")]
+    pub code_rate_limit: Option<RateBudget>,
+
+    /// The budget for sending payloads (eg: `10,30`).
+    #[arg(long, value_name = "BURST,PER_MINUTE", value_parser = parse_rate_budget)]
+    #[serde(rename = "send", skip_serializing_if = "Option::is_none")]
+    pub send_rate_limit: Option<RateBudget>,
+
+    /// The budget for receiving payloads (eg: `5,10`).
+    #[arg(long, value_name = "BURST,PER_MINUTE", value_parser = parse_rate_budget)]
+    #[serde(rename = "receive", skip_serializing_if = "Option::is_none")]
+    pub receive_rate_limit: Option<RateBudget>,
+}
+
+/// Parses a rate limit budget given as a command-line flag.
+///
+/// # Arguments
+///
+/// * `value` - The flag value, as `<burst>,<per minute>` (eg: `10,30`).
+fn parse_rate_budget(value: &str) -> Result<RateBudget, String> {
+    let (burst, per_minute) = value
+        .split_once(',')
+        .ok_or_else(|| "expected '<burst>,<per minute>'".to_string())?;
+
+    Ok(RateBudget {
+        burst: burst
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid burst: {}", e))?,
+        per_minute: per_minute
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid rate per minute: {}", e))?,
+    })
+}
+
+/// Parses a byte size given as a command-line flag.
+///
+/// # Arguments
+///
+/// * `value` - The flag value (eg: `100 MiB`).
+fn parse_byte_unit(value: &str) -> Result<ByteUnit, String> {
+    value.parse().map_err(|e| format!("{}", e))
+}
+
+/// Builds the layered configuration sources of the service.
+///
+/// Sources are merged in increasing order of precedence:
+///
+/// 1. Rocket's defaults (listening on port 8080, and logging at the `normal` level).
+/// 2. `Rocket.toml` (or `$ROCKET_CONFIG`).
+/// 3. `Pylon.toml` (or `$PYLON_CONFIG`, or the `--config` flag), with the same profile tables as
+///    `Rocket.toml` (eg: `[default]`, `[release]`).
+/// 4. The `PORT` environment variable, as set by most hosting platforms.
+/// 5. `ROCKET_`-prefixed environment variables (eg: `ROCKET_ADDRESS`).
+/// 6. `PYLON_`-prefixed environment variables (eg: `PYLON_STATIC_DIR`).
+/// 7. Command-line flags.
+///
+/// # Arguments
+///
+/// * `args` - The command-line flags.
+pub fn figment(args: &ConfigArgs) -> Figment {
+    let config_file = args
+        .config
+        .clone()
+        .unwrap_or_else(|| Env::var_or("PYLON_CONFIG", "Pylon.toml").into());
+
+    let mut figment = Figment::from(Config {
+        port: 8080,
+        log_level: LogLevel::Normal,
+        ..Config::default()
+    })
+    .merge(Toml::file(Env::var_or("ROCKET_CONFIG", "Rocket.toml")).nested())
+    .merge(Toml::file(config_file).nested())
+    .merge(Env::raw().only(&["PORT"]).global())
+    .merge(Env::prefixed("ROCKET_").ignore(&["PROFILE"]).global())
+    .merge(
+        Env::prefixed("PYLON_")
+            .ignore(&["CONFIG", "PASSPHRASE"])
+            .global(),
+    )
+    .merge(Serialized::globals(args));
+
+    if let Some(limit) = args.upload_limit {
+        // Files are uploaded in multipart forms, which must fit the file too.
+        figment = figment
+            .merge(Serialized::global("limits.file", limit))
+            .merge(Serialized::global("limits.data-form", limit));
+    }
+
+    figment.select(Profile::from_env_or(
+        "ROCKET_PROFILE",
+        Config::DEFAULT_PROFILE,
+    ))
+}
+
+/// Extracts and validates the service and server configuration from the configuration sources.
+///
+/// # Arguments
+///
+/// * `figment` - The configuration sources.
+pub fn load(figment: &Figment) -> Result<(PylonConfig, Config), ConfigError> {
+    let server = Config::try_from(figment).map_err(|e| ConfigError(e.to_string()))?;
+    let config = figment
+        .extract::<PylonConfig>()
+        .map_err(|e| ConfigError(e.to_string()))?;
+
+    config.validate()?;
+
+    Ok((config, server))
+}
+
+/// The CORS policy.
+///
+/// Set through the `cors` table of `Pylon.toml` (or `Rocket.toml`), or `PYLON_CORS` (or
+/// `ROCKET_CORS`, eg: `PYLON_CORS='{allowed_origins=["https://*.example.com"]}'`).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// The origins allowed to make cross-origin requests.
+    ///
+    /// Entries are either exact origins (eg: `https://example.com`), wildcard subdomain origins
+    /// (eg: `https://*.example.com`, which doesn't match `https://example.com` itself), or `*` to
+    /// allow any origin.
+    pub allowed_origins: Vec<String>,
+
+    /// The methods allowed in cross-origin requests.
+    pub allowed_methods: Vec<String>,
+
+    /// The request headers allowed in cross-origin requests, or `*` to allow any header.
+    pub allowed_headers: Vec<String>,
+
+    /// The response headers exposed to cross-origin requests.
+    pub exposed_headers: Vec<String>,
+
+    /// Whether cross-origin requests may include credentials (cookies, authorization headers).
+    pub allow_credentials: bool,
+
+    /// Time (in seconds) browsers may cache the result of a preflight request.
+    pub max_age: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".into()],
+            allowed_methods: ["GET", "POST", "DELETE", "OPTIONS"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: vec!["Content-Type".into()],
+            exposed_headers: ["Content-Disposition", "X-File-Size", "X-Checksum-SHA256"]
+                .map(String::from)
+                .to_vec(),
+            allow_credentials: false,
+            max_age: 86400,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Checks that the configured policy is usable.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for origin in &self.allowed_origins {
+            if origin == "*" {
+                continue;
+            }
+
+            let url = Url::parse(&origin.replacen("://*.", "://", 1))
+                .map_err(|e| ConfigError(format!("invalid CORS origin '{}': {}", origin, e)))?;
+
+            if url.host().is_none() || url.path() != "/" || origin.ends_with('/') {
+                return Err(ConfigError(format!(
+                    "invalid CORS origin '{}': expected '<scheme>://<host>[:<port>]'",
+                    origin
+                )));
+            }
+        }
+
+        if self.allow_credentials && self.allowed_origins.iter().any(|origin| origin == "*") {
+            return Err(ConfigError(
+                "CORS credentials cannot be allowed for any origin ('*')".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether an origin may make cross-origin requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `origin` - The value of the request's `Origin` header.
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| {
+            if allowed == "*" {
+                return true;
+            }
+
+            match allowed.split_once("://*.") {
+                Some((scheme, domain)) => origin
+                    .strip_prefix(scheme)
+                    .and_then(|rest| rest.strip_prefix("://"))
+                    .and_then(|host| host.strip_suffix(domain))
+                    .and_then(|sub| sub.strip_suffix('.'))
+                    .is_some_and(|sub| !sub.is_empty() && !sub.contains(['/', ':', '@'])),
+                None => allowed.eq_ignore_ascii_case(origin),
+            }
+        })
+    }
+
+    /// Checks whether a method may be used in cross-origin requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The requested method.
+    pub fn allows_method(&self, method: &str) -> bool {
+        self.allowed_methods
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(method))
+    }
+
+    /// Checks whether all of the given request headers may be sent in cross-origin requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - The comma-separated list of requested headers.
+    pub fn allows_headers(&self, headers: &str) -> bool {
+        headers
+            .split(',')
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .all(|header| {
+                self.allowed_headers
+                    .iter()
+                    .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(header))
+            })
+    }
+}
+
+/// The security headers added to every response.
+///
+/// Set through the `security_headers` table of `Pylon.toml` (or `Rocket.toml`), or
+/// `PYLON_SECURITY_HEADERS` (or `ROCKET_SECURITY_HEADERS`). An empty value disables the
+/// corresponding header (or directive, for `frame_ancestors`).
+///
+/// The default policy allows the frontend bundle (built with `INLINE_RUNTIME_CHUNK=false`) and its
+/// Google Fonts to load, and nothing else.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    /// The `Content-Security-Policy` header, without the `frame-ancestors` directive.
+    pub content_security_policy: String,
+
+    /// The sources allowed to embed the frontend in a frame, appended to the content security
+    /// policy as its `frame-ancestors` directive.
+    pub frame_ancestors: String,
+
+    /// The `Strict-Transport-Security` header.
+    pub strict_transport_security: String,
+
+    /// The `X-Content-Type-Options` header.
+    pub content_type_options: String,
+
+    /// The `Referrer-Policy` header.
+    pub referrer_policy: String,
+
+    /// The `Permissions-Policy` header.
+    pub permissions_policy: String,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            content_security_policy: [
+                "default-src 'self'",
+                "script-src 'self'",
+                "style-src 'self' 'unsafe-inline' https://fonts.googleapis.com",
+                "font-src 'self' https://fonts.gstatic.com",
+                "img-src 'self' data:",
+                "connect-src 'self'",
+                "object-src 'none'",
+                "base-uri 'self'",
+                "form-action 'self'",
+            ]
+            .join("; "),
+            frame_ancestors: "'none'".into(),
+            strict_transport_security: "max-age=63072000; includeSubDomains".into(),
+            content_type_options: "nosniff".into(),
+            referrer_policy: "no-referrer".into(),
+            permissions_policy: "camera=(), microphone=(), geolocation=(), payment=(), usb=()"
+                .into(),
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    /// Returns the configured headers, as (name, value) pairs.
+    ///
+    /// Disabled headers are left out.
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let csp = [
+            self.content_security_policy.trim().trim_end_matches(';'),
+            &self.frame_ancestors_directive(),
+        ]
+        .into_iter()
+        .filter(|directive| !directive.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ");
+
+        [
+            ("Content-Security-Policy", csp),
+            (
+                "Strict-Transport-Security",
+                self.strict_transport_security.clone(),
+            ),
+            ("X-Content-Type-Options", self.content_type_options.clone()),
+            ("Referrer-Policy", self.referrer_policy.clone()),
+            ("Permissions-Policy", self.permissions_policy.clone()),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.trim().is_empty())
+        .collect()
+    }
+
+    /// Returns the `frame-ancestors` directive, or an empty string if it's disabled.
+    fn frame_ancestors_directive(&self) -> String {
+        match self.frame_ancestors.trim() {
+            "" => String::new(),
+            sources => format!("frame-ancestors {}", sources),
+        }
+    }
+}
+
+/// The per-client rate limits.
+///
+/// Set through the `rate_limit` table of `Pylon.toml` (or `Rocket.toml`), `PYLON_RATE_LIMIT` (or
+/// `ROCKET_RATE_LIMIT`, eg: `PYLON_RATE_LIMIT='{receive={burst=3,per_minute=6}}'`), or flags (eg:
+/// `--receive-rate-limit 3,6`, see [`RateLimitArgs`]).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Whether requests are rate limited at all.
+    pub enabled: bool,
+
+    /// The header carrying the client IP address, set by a trusted reverse proxy (eg:
+    /// `X-Forwarded-For`).
+    ///
+    /// If the header holds a list of addresses, the last one (appended by the proxy) is used. If
+    /// unset, or if a request lacks the header, the IP address of the connecting peer is used.
+    ///
+    /// NOTE: Only set this when the service is exclusively reachable through the proxy, since
+    /// clients could otherwise forge the header.
+    pub trusted_proxy_header: Option<String>,
+
+    /// The budget for generating codes (`/code`).
+    pub code: RateBudget,
+
+    /// The budget for sending payloads (`/send`, `/send/file`).
+    pub send: RateBudget,
+
+    /// The budget for receiving payloads (`/receive`, `/receive/file`, `/chat`).
+    ///
+    /// Kept tight by default, since every attempt is a guess at a short code.
+    pub receive: RateBudget,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trusted_proxy_header: None,
+            code: RateBudget {
+                burst: 10,
+                per_minute: 30,
+            },
+            send: RateBudget {
+                burst: 10,
+                per_minute: 30,
+            },
+            receive: RateBudget {
+                burst: 5,
+                per_minute: 10,
+            },
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Checks that the configured limits are usable.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (name, budget) in [
+            ("code", &self.code),
+            ("send", &self.send),
+            ("receive", &self.receive),
+        ] {
+            if budget.burst == 0 || budget.per_minute == 0 {
+                return Err(ConfigError(format!(
+                    "'{}' rate limit must allow at least 1 request (burst and per minute)",
+                    name
+                )));
+            }
+        }
+
+        if let Some(header) = &self.trusted_proxy_header {
+            if header.trim().is_empty() {
+                return Err(ConfigError("trusted proxy header cannot be empty".into()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A token bucket budget.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateBudget {
+    /// The number of requests a client may make in a burst.
+    pub burst: u32,
+
+    /// The number of requests a client may make per minute, once its burst is used up.
+    pub per_minute: u32,
+}
+
+/// The deadlines (in seconds) of wormhole operations.
+///
+/// Set through the `timeouts` table of `Pylon.toml` (or `Rocket.toml`), `PYLON_TIMEOUTS` (or
+/// `ROCKET_TIMEOUTS`, eg: `PYLON_TIMEOUTS='{handshake=30}'`), or flags (eg: `--handshake-timeout
+/// 30`, see [`TimeoutArgs`]).
+///
+/// Senders wait for a receiver for at most the handshake timeout, and never past the expiry of their
+/// code (see `code_ttl`).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Time allowed to connect to the rendezvous server and open the mailbox.
+    pub connect: u64,
+
+    /// Time allowed for the peer to complete the handshake, once the mailbox is open (or once the
+    /// sender starts waiting for a receiver).
+    pub handshake: u64,
+
+    /// Time allowed for the peer to send its payload, once the handshake is complete.
+    pub receive: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            connect: 10,
+            handshake: 60,
+            receive: 30,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    /// Checks that the configured timeouts are usable.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (name, secs) in [
+            ("connect", self.connect),
+            ("handshake", self.handshake),
+            ("receive", self.receive),
+        ] {
+            if secs == 0 {
+                return Err(ConfigError(format!(
+                    "'{}' timeout must be at least 1 second",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the connection timeout.
+    pub fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect)
+    }
+
+    /// Returns the handshake timeout.
+    pub fn handshake(&self) -> Duration {
+        Duration::from_secs(self.handshake)
+    }
+
+    /// Returns the payload receipt timeout.
+    pub fn receive(&self) -> Duration {
+        Duration::from_secs(self.receive)
+    }
+}
@@ -2,4 +2,52 @@
 
 pub const APP_ID: &str = "com.nikhil-prabhu.pylon-web";
 pub const CODE_LENGTH: usize = 2;
+pub const MIN_CODE_LENGTH: usize = 2;
+pub const MAX_CODE_LENGTH: usize = 8;
 pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Time (in seconds) to wait for the rendezvous server to respond at startup.
+pub const RENDEZVOUS_PROBE_TIMEOUT: u64 = 10;
+
+/// Size (in bytes) of the in-memory buffer between a received file and its HTTP download stream.
+pub const FILE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Number of transfer events buffered per code for slow event stream subscribers.
+pub const EVENT_CHANNEL_CAPACITY: usize = 16;
+
+/// Number of words in each of the wordlists wormhole code words are picked from.
+pub const WORDLIST_SIZE: usize = 256;
+
+/// Memory cost (in KiB) of the Argon2id key derivation used for end-to-end encryption.
+pub const KDF_MEMORY_KIB: u32 = 19 * 1024;
+
+/// Number of passes of the Argon2id key derivation used for end-to-end encryption.
+pub const KDF_ITERATIONS: u32 = 2;
+
+/// Degree of parallelism of the Argon2id key derivation used for end-to-end encryption.
+pub const KDF_PARALLELISM: u32 = 1;
+
+/// Highest Argon2id memory cost (in KiB) accepted from a peer, so that decrypting a message can't
+/// exhaust the receiver's memory.
+pub const MAX_KDF_MEMORY_KIB: u32 = 256 * 1024;
+
+/// Highest number of Argon2id passes accepted from a peer.
+pub const MAX_KDF_ITERATIONS: u32 = 16;
+
+/// Size (in bytes) of the random salt of the key derivation used for end-to-end encryption.
+pub const KDF_SALT_LENGTH: usize = 16;
+
+/// Maximum number of times the API client retries a request that failed with a transient error.
+pub const CLIENT_MAX_RETRIES: u32 = 3;
+
+/// Delay (in milliseconds) before the API client's first retry, doubled for each subsequent retry.
+pub const CLIENT_RETRY_BACKOFF: u64 = 200;
+
+/// Prefix the versioned API routes are mounted under.
+pub const API_PREFIX: &str = "/api/v1";
+
+/// Version of the payload schema sent over the wormhole, bumped on incompatible changes.
+pub const PAYLOAD_SCHEMA_VERSION: u32 = 1;
+
+/// Oldest payload schema version that can still be read from a peer.
+pub const MIN_PAYLOAD_SCHEMA_VERSION: u32 = 1;
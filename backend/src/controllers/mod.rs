@@ -1,76 +1,870 @@
 //! API route controllers.
 
-use std::collections::HashMap;
-use std::time::SystemTime;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::Path;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+use futures::future;
 use futures::lock::Mutex;
 
+use rocket::tokio::fs::File;
+use rocket::tokio::io::{self, AsyncRead, AsyncReadExt};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::tokio::time::timeout;
+
+use tokio_util::compat::{
+    FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt,
+};
+
 use unic_segment::Graphemes;
 
 use sha256::digest;
 
-use crate::core::{Mode, Payload, Pylon, PylonError};
-use crate::ThreadSafeError;
+use sha2::{Digest, Sha256};
+
+use crate::config::PylonConfig;
+use crate::consts::{EVENT_CHANNEL_CAPACITY, FILE_BUFFER_SIZE};
+use crate::core::{
+    self, ChatSession, CheckState, ChecksumReader, CodeInfo, HealthCheck, HealthReport, Mode,
+    Payload, Pylon, PylonError, TimeoutStage, TransferEvent, TransferState, TransferStatus,
+};
+use crate::crypto;
+use crate::metrics::{Direction, Metrics};
+use crate::store::{Session, SessionStore};
+
+/// Tracks the state of transfers by wormhole code, so that clients can poll for it or subscribe to
+/// its lifecycle events.
+///
+/// Cloning the tracker is cheap, and all clones share the same state.
+#[derive(Clone, Default)]
+pub struct TransferTracker {
+    /// The transfer statuses, keyed by wormhole code.
+    transfers: Arc<Mutex<HashMap<String, TransferStatus>>>,
+
+    /// The event channels, keyed by wormhole code.
+    events: Arc<Mutex<HashMap<String, EventChannel>>>,
+}
+
+/// The lifecycle events of a transfer.
+struct EventChannel {
+    /// The channel the events are published to.
+    sender: broadcast::Sender<TransferEvent>,
+
+    /// The events published so far, replayed to late subscribers.
+    history: Vec<TransferEvent>,
+}
+
+impl Default for EventChannel {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            history: Vec::new(),
+        }
+    }
+}
+
+impl EventChannel {
+    /// Records an event, and publishes it to the current subscribers.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to publish.
+    fn publish(&mut self, event: TransferEvent) {
+        self.history.push(event.clone());
+
+        // Sending only fails if there are no subscribers, which is fine.
+        let _ = self.sender.send(event);
+    }
+}
+
+impl TransferTracker {
+    /// Records a new state for a transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    /// * `state` - The new state.
+    /// * `error` - The reason of the failure (only for the failed state).
+    pub async fn update(&self, code: &str, state: TransferState, error: Option<String>) {
+        let mut transfers = self.transfers.lock().await;
+        transfers.insert(
+            code.into(),
+            TransferStatus {
+                code: code.into(),
+                state,
+                error,
+                updated_at: SystemTime::now(),
+            },
+        );
+    }
+
+    /// Returns the status of a transfer, if it is known.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    pub async fn get(&self, code: &str) -> Option<TransferStatus> {
+        let transfers = self.transfers.lock().await;
+        transfers.get(code).cloned()
+    }
+
+    /// Publishes a lifecycle event to the subscribers of a transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    /// * `event` - The event to publish.
+    pub async fn emit(&self, code: &str, event: TransferEvent) {
+        let mut events = self.events.lock().await;
+        events.entry(code.into()).or_default().publish(event);
+    }
+
+    /// Subscribes to the lifecycle events of a transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    pub async fn subscribe(&self, code: &str) -> broadcast::Receiver<TransferEvent> {
+        self.subscribe_with_history(code).await.1
+    }
+
+    /// Subscribes to the lifecycle events of a transfer, and returns the events published before
+    /// the subscription.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    pub async fn subscribe_with_history(
+        &self,
+        code: &str,
+    ) -> (Vec<TransferEvent>, broadcast::Receiver<TransferEvent>) {
+        let mut events = self.events.lock().await;
+        let channel = events.entry(code.into()).or_default();
+
+        (channel.history.clone(), channel.sender.subscribe())
+    }
+
+    /// Forgets transfers whose state hasn't changed for longer than `max_age`, along with the
+    /// event channels of forgotten transfers that have no subscribers left.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age` - How long a transfer status is kept after its last state change.
+    pub async fn prune(&self, max_age: Duration) {
+        let mut transfers = self.transfers.lock().await;
+        transfers.retain(|_, status| {
+            status
+                .updated_at
+                .elapsed()
+                .map_or(true, |elapsed| elapsed < max_age)
+        });
+
+        let mut events = self.events.lock().await;
+        events.retain(|code, channel| {
+            transfers.contains_key(code) || channel.sender.receiver_count() > 0
+        });
+    }
+
+    /// Records a failed transfer, and notifies its subscribers.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    /// * `error` - The reason of the failure.
+    async fn fail(&self, code: &str, error: &PylonError) {
+        self.update(code, TransferState::Failed, Some(error.to_string()))
+            .await;
+        self.emit_error(code, error).await;
+    }
+
+    /// Notifies the subscribers of a transfer that it failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    /// * `error` - The reason of the failure.
+    async fn emit_error(&self, code: &str, error: &PylonError) {
+        let event = TransferEvent::Error {
+            message: error.to_string(),
+        };
+        self.emit(code, event).await;
+    }
+
+    /// Waits until the sender cancels a transfer, which may never happen.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    async fn cancellation(&self, code: &str) {
+        let mut rx = self.subscribe(code).await;
+
+        if self.get(code).await.map(|status| status.state) == Some(TransferState::Cancelled) {
+            return;
+        }
+
+        loop {
+            match rx.recv().await {
+                Ok(TransferEvent::Cancelled) => return,
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return future::pending().await,
+            }
+        }
+    }
+
+    /// Notifies the subscribers of a transfer that the peers completed their handshake.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the transfer.
+    async fn handshake_done(&self, code: &str) {
+        // The handshake (PAKE exchange followed by key confirmation) is performed as a single step by
+        // the wormhole, so both events are published once it completes. When both peers use this
+        // server, each of them completes the handshake, but the events are only published once.
+        let mut events = self.events.lock().await;
+        let channel = events.entry(code.into()).or_default();
+
+        if !channel.history.contains(&TransferEvent::PeerConnected) {
+            channel.publish(TransferEvent::PeerConnected);
+            channel.publish(TransferEvent::KeyConfirmed);
+        }
+    }
+}
 
-lazy_static! {
-    static ref PYLON_MAP: Mutex<HashMap<String, Pylon>> = Mutex::new(HashMap::new());
+async fn get_pylon(code_length: usize, config: &PylonConfig) -> Result<Pylon, PylonError> {
+    Pylon::with_code_length(code_length, config).await
 }
 
-async fn get_pylon() -> Result<Pylon, ThreadSafeError> {
-    Pylon::new(Mode::Sender, None).await
+/// Returns the time left until a deadline, or zero if it has passed.
+///
+/// # Arguments
+///
+/// * `deadline` - The deadline.
+fn remaining(deadline: SystemTime) -> Duration {
+    deadline
+        .duration_since(SystemTime::now())
+        .unwrap_or_default()
 }
 
 /// Generates a wormhole code.
-/// The newly created FutureConn will be pushed into a global Pylon map to be re-used later.
-pub async fn gen_code() -> Result<String, ThreadSafeError> {
-    let pylon = get_pylon().await?;
+/// The newly created FutureConn will be pushed into the session store to be re-used later, until
+/// it expires.
+///
+/// # Arguments
+///
+/// * `words` - The number of words in the code, or `None` for the configured default. Must be
+///   within the configured bounds.
+/// * `store` - The session store for pending senders.
+/// * `tracker` - The transfer state tracker.
+/// * `metrics` - The service metrics.
+/// * `config` - The service configuration.
+pub async fn gen_code(
+    words: Option<usize>,
+    store: &dyn SessionStore,
+    tracker: &TransferTracker,
+    metrics: &Metrics,
+    config: &PylonConfig,
+) -> Result<CodeInfo, PylonError> {
+    let words = words.unwrap_or(config.code_length);
+
+    if !(config.min_code_length..=config.max_code_length).contains(&words) {
+        return Err(PylonError::InvalidCodeLength(format!(
+            "codes must have between {} and {} words",
+            config.min_code_length, config.max_code_length
+        )));
+    }
+
+    let pylon = get_pylon(words, config)
+        .await
+        .inspect_err(|e| metrics.error(e))?;
     let code = pylon.code.clone();
 
     if let Some(code) = code {
-        let mut pylon_map = PYLON_MAP.lock().await;
-        pylon_map.insert(code.clone(), pylon);
+        metrics.code_generated();
+
+        let ttl = Duration::from_secs(config.code_ttl);
+        let expires_at = store.insert(code.clone(), pylon, ttl).await;
+        tracker.update(&code, TransferState::Pending, None).await;
+        tracker.emit(&code, TransferEvent::MailboxAllocated).await;
 
-        return Ok(code);
+        return Ok(CodeInfo {
+            code,
+            expires_at,
+            words,
+            entropy_bits: CodeInfo::entropy(words),
+        });
     }
 
-    Ok(String::new())
+    Err(PylonError::Internal("Code generation failed".into()))
 }
 
-/// Sends a payload through an encrypted wormhole tunnel.
+/// Cancels a pending sender, releasing its nameplate and closing its mailbox on the rendezvous
+/// server, and notifies the receivers waiting on its code.
+///
+/// NOTE: Receivers connected to other instances of the service (or using other wormhole clients)
+/// aren't notified, and give up once their handshake times out.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code to cancel.
+/// * `store` - The session store for pending senders.
+/// * `tracker` - The transfer state tracker.
+pub async fn cancel_code(
+    code: &str,
+    store: &dyn SessionStore,
+    tracker: &TransferTracker,
+) -> Result<TransferStatus, PylonError> {
+    let session = store.take(code).await.ok_or(PylonError::UnknownCode)?;
+
+    // The code is cancelled as soon as it's taken, even if the rendezvous server can't be reached.
+    tracker
+        .update(
+            code,
+            TransferState::Cancelled,
+            Some(PylonError::Cancelled.to_string()),
+        )
+        .await;
+    tracker.emit(code, TransferEvent::Cancelled).await;
+
+    if let Err(e) = session.pylon.close().await {
+        warn!("Failed to close a cancelled code: {}", e);
+    }
+
+    transfer_status(code, tracker).await
+}
+
+/// Enqueues a payload to be sent through an encrypted wormhole tunnel.
+///
+/// The transfer runs in the background, and its progress is recorded in the transfer tracker. If no
+/// receiver connects before the code expires, the transfer expires (and it fails if the handshake
+/// timeout elapses first).
 ///
 /// # Arguments
 ///
 /// * `payload` - The payload to send.
-pub async fn send_payload(mut payload: Payload) -> Result<Payload, ThreadSafeError> {
-    let mut pylon_map = PYLON_MAP.lock().await;
-    let pylon = pylon_map.remove(&payload.code);
+/// * `store` - The session store for pending senders.
+/// * `tracker` - The transfer state tracker.
+/// * `metrics` - The service metrics.
+/// * `config` - The service configuration.
+/// * `abort` - Resolves when the request is aborted (eg: when the server shuts down).
+pub async fn send_payload(
+    mut payload: Payload,
+    store: &dyn SessionStore,
+    tracker: &TransferTracker,
+    metrics: &Metrics,
+    config: &PylonConfig,
+    abort: impl Future<Output = ()> + Send + 'static,
+) -> Result<Payload, PylonError> {
+    let message = payload
+        .message
+        .as_ref()
+        .ok_or_else(|| PylonError::EmptyPayload("Message cannot be empty".into()))
+        .inspect_err(|e| metrics.transfer_failed(Direction::Send, e))?;
+    let size = message.len() as u64;
+
+    crypto::validate(&payload).inspect_err(|e| metrics.transfer_failed(Direction::Send, e))?;
 
-    if let Some(pylon) = pylon {
-        payload.time = Some(SystemTime::now());
+    payload.time = Some(SystemTime::now());
+    payload.length = Some(Graphemes::new(message).count());
+    payload.checksum = Some(digest(message));
 
-        if let Some(message) = &payload.message {
-            payload.length = Some(Graphemes::new(message).count());
-            payload.checksum = Some(digest(message));
+    let Session {
+        mut pylon,
+        expires_at,
+    } = store
+        .take(&payload.code)
+        .await
+        .ok_or(PylonError::UnknownCode)
+        .inspect_err(|e| metrics.transfer_failed(Direction::Send, e))?;
+
+    tracker
+        .update(&payload.code, TransferState::Pending, None)
+        .await;
+
+    let tracker = tracker.clone();
+    let metrics = metrics.clone();
+    let handshake_timeout = config.timeouts.handshake();
+    let queued = payload.clone();
+    rocket::tokio::spawn(async move {
+        let code = queued.code.as_str();
+        let mut abort = pin!(abort);
+        let connected = connect_sender(&mut pylon, expires_at, handshake_timeout, &metrics);
+
+        match abortable(connected, &mut abort).await {
+            Ok(()) => {
+                tracker.update(code, TransferState::Connected, None).await;
+                tracker.handshake_done(code).await;
+            }
+            Err(e) if is_expiry(&e, expires_at) => {
+                metrics.transfer_failed(Direction::Send, &e);
+                tracker.update(code, TransferState::Expired, None).await;
+                return tracker.emit(code, TransferEvent::Expired).await;
+            }
+            Err(e) => {
+                metrics.transfer_failed(Direction::Send, &e);
+                return tracker.fail(code, &e).await;
+            }
         }
 
-        pylon.activate(Some(&payload)).await?;
-    }
+        match abortable(pylon.activate(Some(&queued)), abort).await {
+            Ok(_) => {
+                metrics.transfer_succeeded(Direction::Send, size);
+                tracker.update(code, TransferState::Delivered, None).await;
+                tracker.emit(code, TransferEvent::PayloadSent).await;
+            }
+            Err(e) => {
+                metrics.transfer_failed(Direction::Send, &e);
+                tracker.fail(code, &e).await;
+            }
+        }
+    });
 
     Ok(payload)
 }
 
+/// Returns the status of a transfer.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code of the transfer.
+/// * `tracker` - The transfer state tracker.
+pub async fn transfer_status(
+    code: &str,
+    tracker: &TransferTracker,
+) -> Result<TransferStatus, PylonError> {
+    tracker.get(code).await.ok_or(PylonError::UnknownCode)
+}
+
 /// Receives a payload through an encrypted wormhole tunnel.
 ///
 /// # Arguments
 ///
 /// * `code` - The wormhole code to use for PAKE authentication.
-pub async fn receive_payload(code: String) -> Result<Payload, ThreadSafeError> {
-    let pylon = Pylon::new(Mode::Receiver, Some(code)).await?;
-    let payload = pylon.activate(None).await?;
+/// * `tracker` - The transfer state tracker.
+/// * `metrics` - The service metrics.
+/// * `config` - The service configuration.
+/// * `abort` - Resolves when the request is aborted (eg: when the server shuts down).
+pub async fn receive_payload(
+    code: String,
+    tracker: &TransferTracker,
+    metrics: &Metrics,
+    config: &PylonConfig,
+    abort: impl Future<Output = ()>,
+) -> Result<Payload, PylonError> {
+    let received = async {
+        let pylon = connect_receiver(&code, tracker, metrics, config).await?;
+        tracker.handshake_done(&code).await;
+
+        pylon
+            .activate(None)
+            .await?
+            .ok_or_else(|| PylonError::EmptyPayload("Received empty payload".into()))
+    };
+    let res = abortable(received, abort).await;
+
+    match res {
+        Ok(payload) => {
+            let size = payload.message.as_ref().map_or(0, |message| message.len());
+            metrics.transfer_succeeded(Direction::Receive, size as u64);
+            tracker.emit(&code, TransferEvent::PayloadReceived).await;
+            Ok(payload)
+        }
+        Err(e) => {
+            metrics.transfer_failed(Direction::Receive, &e);
+            tracker.emit_error(&code, &e).await;
+            Err(e)
+        }
+    }
+}
+
+/// Sends a file through an encrypted wormhole tunnel.
+///
+/// If no receiver connects before the code expires, the transfer expires (and it fails if the
+/// handshake timeout elapses first).
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code generated for the sender.
+/// * `file_name` - The name of the file, as presented to the receiver.
+/// * `path` - The path of the file on disk.
+/// * `store` - The session store for pending senders.
+/// * `tracker` - The transfer state tracker.
+/// * `metrics` - The service metrics.
+/// * `config` - The service configuration.
+/// * `abort` - Resolves when the request is aborted (eg: when the server shuts down).
+#[allow(clippy::too_many_arguments)]
+pub async fn send_file(
+    code: String,
+    file_name: String,
+    path: &Path,
+    store: &dyn SessionStore,
+    tracker: &TransferTracker,
+    metrics: &Metrics,
+    config: &PylonConfig,
+    abort: impl Future<Output = ()>,
+) -> Result<Payload, PylonError> {
+    // Hash the file before taking the pending sender, so that an I/O error doesn't use up the code.
+    let mut file = File::open(path).await?;
+    let (size, checksum) = file_digest(&mut file).await?;
+
+    let Session {
+        mut pylon,
+        expires_at,
+    } = store
+        .take(&code)
+        .await
+        .ok_or(PylonError::UnknownCode)
+        .inspect_err(|e| metrics.transfer_failed(Direction::Send, e))?;
+
+    let payload = Payload {
+        code,
+        time: Some(SystemTime::now()),
+        checksum: Some(checksum),
+        file_name: Some(file_name),
+        size: Some(size),
+        ..Default::default()
+    };
+
+    let mut file = File::open(path).await?.compat();
+
+    let mut abort = pin!(abort);
+    let connected = connect_sender(&mut pylon, expires_at, config.timeouts.handshake(), metrics);
+    let connected = match abortable(connected, &mut abort).await {
+        Err(e) if is_expiry(&e, expires_at) => {
+            metrics.transfer_failed(Direction::Send, &e);
+            tracker
+                .update(&payload.code, TransferState::Expired, None)
+                .await;
+            tracker.emit(&payload.code, TransferEvent::Expired).await;
+
+            return Err(e);
+        }
+        connected => connected,
+    };
+
+    let sent = async {
+        connected?;
+        tracker
+            .update(&payload.code, TransferState::Connected, None)
+            .await;
+        tracker.handshake_done(&payload.code).await;
+
+        pylon.send_file(&payload, &mut file, config).await
+    };
+    let res = abortable(sent, abort).await;
+
+    match res {
+        Ok(()) => {
+            metrics.transfer_succeeded(Direction::Send, size);
+            tracker
+                .update(&payload.code, TransferState::Delivered, None)
+                .await;
+            tracker
+                .emit(&payload.code, TransferEvent::PayloadSent)
+                .await;
+
+            Ok(payload)
+        }
+        Err(e) => {
+            metrics.transfer_failed(Direction::Send, &e);
+            tracker.fail(&payload.code, &e).await;
+
+            Err(e)
+        }
+    }
+}
+
+/// Receives a file through an encrypted wormhole tunnel.
+///
+/// Returns the file metadata announced by the sender, along with a reader that streams the file
+/// contents as they arrive. The reader fails if the received contents do not match the announced
+/// size and checksum.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code to use for PAKE authentication.
+/// * `tracker` - The transfer state tracker.
+/// * `metrics` - The service metrics.
+/// * `config` - The service configuration.
+/// * `abort` - Resolves when the request is aborted (eg: when the server shuts down), until the
+///   download starts.
+pub async fn receive_file(
+    code: String,
+    tracker: &TransferTracker,
+    metrics: &Metrics,
+    config: &PylonConfig,
+    abort: impl Future<Output = ()>,
+) -> Result<(Payload, impl AsyncRead + Send), PylonError> {
+    let offered = async {
+        let pylon = connect_receiver(&code, tracker, metrics, config).await?;
+        tracker.handshake_done(&code).await;
+
+        pylon.receive_file(config).await
+    };
+    let res = abortable(offered, abort).await;
+
+    let offer = match res {
+        Ok(offer) => offer,
+        Err(e) => {
+            metrics.transfer_failed(Direction::Receive, &e);
+            tracker.emit_error(&code, &e).await;
+            return Err(e);
+        }
+    };
+    let payload = offer.payload.clone();
+    let size = payload.size.unwrap_or_default();
+
+    let (writer, reader) = io::duplex(FILE_BUFFER_SIZE);
+    let tracker = tracker.clone();
+    let metrics = metrics.clone();
+    rocket::tokio::spawn(async move {
+        match offer.accept(&mut writer.compat_write()).await {
+            Ok(()) => {
+                metrics.transfer_succeeded(Direction::Receive, size);
+                tracker.emit(&code, TransferEvent::PayloadReceived).await;
+            }
+            Err(e) => {
+                error!("File transfer failed: {}", e);
+                metrics.transfer_failed(Direction::Receive, &e);
+                tracker.emit_error(&code, &e).await;
+            }
+        }
+    });
+
+    let reader = ChecksumReader::new(
+        reader.compat(),
+        size,
+        payload.checksum.clone().unwrap_or_default(),
+    );
+
+    Ok((payload, reader.compat()))
+}
+
+/// Opens a persistent chat session through an encrypted wormhole tunnel.
+///
+/// If the code was generated by this service, the session is opened on the sending side (as the
+/// pending sender), otherwise it is joined on the receiving side. If no receiver connects before
+/// the code expires or the handshake timeout elapses, the session times out.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code to use for PAKE authentication.
+/// * `store` - The session store for pending senders.
+/// * `tracker` - The transfer state tracker.
+/// * `metrics` - The service metrics.
+/// * `config` - The service configuration.
+/// * `abort` - Resolves when the request is aborted (eg: when the server shuts down or the client
+///   disconnects), until the session is open.
+pub async fn open_chat(
+    code: String,
+    store: &dyn SessionStore,
+    tracker: &TransferTracker,
+    metrics: &Metrics,
+    config: &PylonConfig,
+    abort: impl Future<Output = ()>,
+) -> Result<ChatSession, PylonError> {
+    let opened = async {
+        let pylon = match store.take(&code).await {
+            Some(Session {
+                mut pylon,
+                expires_at,
+            }) => {
+                connect_sender(&mut pylon, expires_at, config.timeouts.handshake(), metrics)
+                    .await?;
+                pylon
+            }
+            None => connect_receiver(&code, tracker, metrics, config).await?,
+        };
+
+        pylon.into_session().await
+    };
+    let res = abortable(opened, abort).await;
+
+    match res {
+        Ok(session) => {
+            tracker.update(&code, TransferState::Connected, None).await;
+            tracker.handshake_done(&code).await;
+
+            Ok(session)
+        }
+        Err(e) => {
+            metrics.error(&e);
+            tracker.fail(&code, &e).await;
+
+            Err(e)
+        }
+    }
+}
+
+/// Waits for a pending sender Pylon to complete the handshake with its receiver, recording how long
+/// it took.
+///
+/// Receivers may only connect for as long as the code remains valid, and within the handshake
+/// timeout.
+///
+/// # Arguments
+///
+/// * `pylon` - The pending sender.
+/// * `expires_at` - The expiry time of the sender's code.
+/// * `handshake_timeout` - The configured handshake timeout.
+/// * `metrics` - The service metrics.
+async fn connect_sender(
+    pylon: &mut Pylon,
+    expires_at: SystemTime,
+    handshake_timeout: Duration,
+    metrics: &Metrics,
+) -> Result<(), PylonError> {
+    let started = Instant::now();
+    timeout(
+        handshake_timeout.min(remaining(expires_at)),
+        pylon.connect(),
+    )
+    .await
+    .map_err(|_| PylonError::Timeout(TimeoutStage::Handshake))??;
+    metrics.handshake_completed(started.elapsed());
+
+    Ok(())
+}
+
+/// Checks whether a sender's handshake timed out because its code expired, rather than because the
+/// handshake timeout elapsed.
+///
+/// # Arguments
+///
+/// * `error` - The error the handshake failed with.
+/// * `expires_at` - The expiry time of the sender's code.
+fn is_expiry(error: &PylonError, expires_at: SystemTime) -> bool {
+    matches!(error, PylonError::Timeout(TimeoutStage::Handshake)) && remaining(expires_at).is_zero()
+}
+
+/// Connects a receiver Pylon to its sender, recording how long the handshake took.
+///
+/// Gives up as soon as the sender cancels the code.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code to use for PAKE authentication.
+/// * `tracker` - The transfer state tracker.
+/// * `metrics` - The service metrics.
+/// * `config` - The service configuration.
+async fn connect_receiver(
+    code: &str,
+    tracker: &TransferTracker,
+    metrics: &Metrics,
+    config: &PylonConfig,
+) -> Result<Pylon, PylonError> {
+    let started = Instant::now();
+    let pylon = select! {
+        pylon = Pylon::new(Mode::Receiver, Some(code.into()), config) => pylon?,
+        _ = tracker.cancellation(code) => return Err(PylonError::Cancelled),
+    };
+    metrics.handshake_completed(started.elapsed());
+
+    Ok(pylon)
+}
+
+/// Runs a wormhole operation until it completes, or until the request is aborted.
+///
+/// Aborting drops the operation, which closes its wormhole cleanly, unless its handshake is in
+/// progress (see [`Pylon::close`]).
+///
+/// # Arguments
+///
+/// * `operation` - The wormhole operation.
+/// * `abort` - Resolves when the request is aborted.
+async fn abortable<T>(
+    operation: impl Future<Output = Result<T, PylonError>>,
+    abort: impl Future<Output = ()>,
+) -> Result<T, PylonError> {
+    select! {
+        res = operation => res,
+        _ = abort => Err(PylonError::Aborted),
+    }
+}
+
+/// Reports the liveness of the service.
+///
+/// There are no checks: the service is alive as long as it can answer.
+pub fn health() -> HealthReport {
+    HealthReport::new(BTreeMap::new())
+}
+
+/// Reports the readiness of the service, by probing the rendezvous server and the session store.
+///
+/// # Arguments
+///
+/// * `store` - The session store.
+/// * `config` - The service configuration.
+pub async fn readiness(store: &dyn SessionStore, config: &PylonConfig) -> HealthReport {
+    let probe_timeout = Duration::from_secs(config.readiness_timeout);
+    let rendezvous = async {
+        timeout(probe_timeout, core::probe_rendezvous(config))
+            .await
+            .unwrap_or_else(|_| {
+                Err(PylonError::RendezvousUnreachable(format!(
+                    "no response within {} seconds",
+                    config.readiness_timeout
+                )))
+            })
+    };
+    let (rendezvous, store) = futures::join!(run_check(rendezvous), run_check(store.ping()));
+
+    HealthReport::new(BTreeMap::from([
+        ("rendezvous".into(), rendezvous),
+        ("store".into(), store),
+    ]))
+}
+
+/// Runs a health check, timing it.
+///
+/// # Arguments
+///
+/// * `check` - The check to run.
+async fn run_check<F>(check: F) -> HealthCheck
+where
+    F: Future<Output = Result<(), PylonError>>,
+{
+    let started = Instant::now();
+    let res = check.await;
+    let duration_ms = started.elapsed().as_millis() as u64;
 
-    if let Some(payload) = payload {
-        Ok(payload)
-    } else {
-        Err(Box::new(PylonError("Received empty payload".into())))
+    match res {
+        Ok(()) => HealthCheck {
+            state: CheckState::Ok,
+            duration_ms,
+            error: None,
+        },
+        Err(e) => HealthCheck {
+            state: CheckState::Failed,
+            duration_ms,
+            error: Some(e.to_string()),
+        },
     }
 }
+
+/// Computes the size and SHA256 checksum of a file.
+///
+/// # Arguments
+///
+/// * `file` - The file, read from its current position.
+pub async fn file_digest(file: &mut File) -> Result<(u64, String), PylonError> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; FILE_BUFFER_SIZE];
+    let mut size = 0;
+
+    loop {
+        let n = file.read(&mut buf).await?;
+
+        if n == 0 {
+            break;
+        }
+
+        size += n as u64;
+        hasher.update(&buf[..n]);
+    }
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
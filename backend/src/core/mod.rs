@@ -1,22 +1,42 @@
 //! The core message sending/receiving functionality.
 
-use std::borrow::Cow;
+mod wordlist;
+
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt;
 use std::future::Future;
+use std::io;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::SystemTime;
 
-use magic_wormhole::rendezvous::DEFAULT_RENDEZVOUS_SERVER;
-use magic_wormhole::{AppConfig, AppID, Code, Wormhole, WormholeError};
+use futures::future;
+use futures::io::{AsyncRead, AsyncWrite};
+
+use magic_wormhole::rendezvous::{RendezvousError, RendezvousServer};
+use magic_wormhole::transfer::{self, ReceiveRequest, TransferError};
+use magic_wormhole::transit::Abilities;
+use magic_wormhole::{AppID, Code, Wormhole, WormholeError};
 
 use serde::{Deserialize, Serialize};
 
+use utoipa::ToSchema;
+
 use unic_segment::Graphemes;
 
 use sha256::digest;
 
-use crate::consts::{APP_ID, APP_VERSION, CODE_LENGTH};
-use crate::ThreadSafeError;
+use sha2::{Digest, Sha256};
+
+use rocket::tokio::runtime::Handle;
+use rocket::tokio::time::timeout;
+
+use crate::config::{AppVersion, ConfigError, PylonConfig, TimeoutConfig};
+use crate::consts::{MIN_PAYLOAD_SCHEMA_VERSION, PAYLOAD_SCHEMA_VERSION, WORDLIST_SIZE};
+use crate::crypto::{self, Encryption};
 
 /// A connection that hasn't yet been established.
 /// It must be awaited to perform the client-client handshake and establish the connection.
@@ -27,20 +47,197 @@ type EstConn = Wormhole;
 
 /// A custom error type for Pylon errors.
 #[derive(Debug, Serialize)]
-pub struct PylonError(pub String);
+pub enum PylonError {
+    /// No pending sender exists for the given wormhole code.
+    UnknownCode,
+
+    /// The wormhole code is malformed, or the PAKE handshake failed because the peers used
+    /// different codes.
+    BadCode,
+
+    /// A wormhole operation did not complete within its configured timeout.
+    Timeout(TimeoutStage),
+
+    /// The sender cancelled the transfer.
+    Cancelled,
+
+    /// The request was aborted (eg: because the server is shutting down) before the transfer
+    /// completed.
+    Aborted,
+
+    /// The rendezvous server could not be reached or returned an error.
+    RendezvousUnreachable(String),
+
+    /// The payload is missing a required value.
+    EmptyPayload(String),
+
+    /// The payload exceeds the allowed size.
+    PayloadTooLarge,
+
+    /// The payload could not be parsed.
+    InvalidPayload(String),
+
+    /// The requested code length is out of the allowed bounds.
+    InvalidCodeLength(String),
+
+    /// The received payload doesn't match its announced length or checksum.
+    Integrity(String),
+
+    /// A message could not be encrypted or decrypted (eg: because of a wrong passphrase).
+    Crypto(String),
+
+    /// The peer sent a payload in a schema version this service can't read.
+    UnsupportedSchemaVersion(u32),
+
+    /// The client made too many requests, and has to wait for the given number of seconds.
+    RateLimited(u64),
+
+    /// Any other failure.
+    Internal(String),
+}
+
+impl PylonError {
+    /// Returns a stable, machine-readable identifier for the error.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::UnknownCode => "unknown_code",
+            Self::BadCode => "bad_code",
+            Self::Timeout(stage) => stage.error_code(),
+            Self::Cancelled => "cancelled",
+            Self::Aborted => "aborted",
+            Self::RendezvousUnreachable(_) => "rendezvous_unreachable",
+            Self::EmptyPayload(_) => "empty_payload",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::InvalidPayload(_) => "invalid_payload",
+            Self::InvalidCodeLength(_) => "invalid_code_length",
+            Self::Integrity(_) => "integrity_error",
+            Self::Crypto(_) => "crypto_error",
+            Self::UnsupportedSchemaVersion(_) => "unsupported_schema_version",
+            Self::RateLimited(_) => "rate_limited",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+}
 
 impl fmt::Display for PylonError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+        match self {
+            Self::UnknownCode => write!(f, "No pending sender found for the given code"),
+            Self::BadCode => write!(f, "Invalid wormhole code"),
+            Self::Timeout(stage) => write!(f, "Timed out {}", stage),
+            Self::Cancelled => write!(f, "The transfer was cancelled by the sender"),
+            Self::Aborted => write!(f, "The request was aborted before the transfer completed"),
+            Self::RendezvousUnreachable(e) => write!(f, "Rendezvous server error: {}", e),
+            Self::EmptyPayload(e) => write!(f, "{}", e),
+            Self::PayloadTooLarge => write!(f, "Payload exceeds the allowed size"),
+            Self::InvalidPayload(e) => write!(f, "Invalid payload: {}", e),
+            Self::InvalidCodeLength(e) => write!(f, "Invalid code length: {}", e),
+            Self::Integrity(e) => write!(f, "Integrity check failed: {}", e),
+            Self::Crypto(e) => write!(f, "Encryption error: {}", e),
+            Self::UnsupportedSchemaVersion(version) => write!(
+                f,
+                "The peer sent a payload in schema version {}, but only versions {} to {} are \
+                 supported; the peers must run compatible versions of pylon-web",
+                version, MIN_PAYLOAD_SCHEMA_VERSION, PAYLOAD_SCHEMA_VERSION
+            ),
+            Self::RateLimited(secs) => {
+                write!(f, "Too many requests, retry in {} second(s)", secs)
+            }
+            Self::Internal(e) => write!(f, "{}", e),
+        }
     }
 }
 
 impl Error for PylonError {}
 
+/// The wormhole operation that timed out.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutStage {
+    /// Connecting to the rendezvous server and opening the mailbox.
+    Connect,
+
+    /// Waiting for the peer to complete the handshake.
+    Handshake,
+
+    /// Waiting for the peer to send its payload.
+    Receive,
+}
+
+impl TimeoutStage {
+    /// Returns a stable, machine-readable identifier for the timeout.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::Connect => "connect_timeout",
+            Self::Handshake => "handshake_timeout",
+            Self::Receive => "receive_timeout",
+        }
+    }
+}
+
+impl fmt::Display for TimeoutStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect => write!(f, "connecting to the rendezvous server"),
+            Self::Handshake => write!(f, "waiting for the peer to complete the handshake"),
+            Self::Receive => write!(f, "waiting for the peer to send its payload"),
+        }
+    }
+}
+
+impl From<WormholeError> for PylonError {
+    fn from(e: WormholeError) -> Self {
+        match e {
+            WormholeError::PakeFailed | WormholeError::Crypto => Self::BadCode,
+            WormholeError::ServerError(e) => Self::RendezvousUnreachable(e.to_string()),
+            WormholeError::ProtocolJson(e) => Self::InvalidPayload(e.to_string()),
+            e => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<TransferError> for PylonError {
+    fn from(e: TransferError) -> Self {
+        match e {
+            TransferError::Wormhole(e) => e.into(),
+            TransferError::PeerError(e) => Self::Internal(format!("Peer error: {}", e)),
+            e => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PylonError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::InvalidPayload(e.to_string())
+    }
+}
+
+impl From<io::Error> for PylonError {
+    fn from(e: io::Error) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+impl From<ConfigError> for PylonError {
+    fn from(e: ConfigError) -> Self {
+        Self::Internal(e.to_string())
+    }
+}
+
+/// A point in time, as serialized by the API (the schema of [`SystemTime`] values).
+#[derive(ToSchema)]
+pub struct Timestamp {
+    /// The number of seconds since the Unix epoch.
+    pub secs_since_epoch: u64,
+
+    /// The number of nanoseconds since the last whole second.
+    pub nanos_since_epoch: u32,
+}
+
 /// Represents the message payload.
 ///
 /// This payload can be sent and received through the encrypted wormhole tunnel.
-#[derive(Serialize, Deserialize, PartialEq, Default, Debug)]
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Default, Debug)]
 pub struct Payload {
     /// The message to send (sender mode)/that was received (receiver mode).
     pub message: Option<String>,
@@ -52,10 +249,39 @@ pub struct Payload {
     pub code: String,
 
     /// The time the message was sent.
+    #[schema(value_type = Option<Timestamp>)]
     pub time: Option<SystemTime>,
 
-    /// The SHA256 checksum of the message.
+    /// The SHA256 checksum of the message (or of the file contents, for file transfers).
     pub checksum: Option<String>,
+
+    /// The name of the transferred file (file transfers only).
+    pub file_name: Option<String>,
+
+    /// The size of the transferred file in bytes (file transfers only).
+    pub size: Option<u64>,
+
+    /// The position of the message in its direction of a chat session, starting at 0 (chat
+    /// sessions only).
+    pub seq: Option<u64>,
+
+    /// How the message was encrypted by the sending client, if it was (see [`crate::crypto`]).
+    ///
+    /// The message of an encrypted payload is the base64-encoded ciphertext, which the service
+    /// relays without being able to decrypt it.
+    pub encryption: Option<Encryption>,
+
+    /// Whether the message was checked against its length and checksum on receipt.
+    ///
+    /// Always set by the receiving side; the value sent by the peer is ignored.
+    #[serde(default)]
+    pub verified: bool,
+
+    /// The version of the payload schema, stamped by the sending side.
+    ///
+    /// Payloads from peers that predate schema versioning have none, and are read as version 1.
+    #[serde(default)]
+    pub schema_version: Option<u32>,
 }
 
 impl From<(&str, &str)> for Payload {
@@ -74,11 +300,253 @@ impl From<(&str, &str)> for Payload {
             code: values.1.into(),
             time: Some(SystemTime::now()),
             checksum: Some(digest(values.0)),
+            file_name: None,
+            size: None,
+            seq: None,
+            encryption: None,
+            verified: false,
+            schema_version: None,
+        }
+    }
+}
+
+impl Payload {
+    /// Checks the message against its announced length and checksum, and marks the payload as
+    /// verified.
+    pub fn verify(&mut self) -> Result<(), PylonError> {
+        let message = self
+            .message
+            .as_deref()
+            .ok_or_else(|| PylonError::EmptyPayload("Received empty payload".into()))?;
+
+        let length = Graphemes::new(message).count();
+        if self.length != Some(length) {
+            return Err(PylonError::Integrity(format!(
+                "expected length {:?}, got {}",
+                self.length, length
+            )));
+        }
+
+        let checksum = digest(message);
+        if self.checksum.as_deref() != Some(checksum.as_str()) {
+            return Err(PylonError::Integrity(format!(
+                "expected checksum {:?}, got {}",
+                self.checksum, checksum
+            )));
+        }
+
+        self.verified = true;
+
+        Ok(())
+    }
+
+    /// Checks that the payload's schema version can be read by this service.
+    pub fn check_schema_version(&self) -> Result<(), PylonError> {
+        // Unversioned payloads were sent by peers that predate versioning, ie: in version 1.
+        let version = self.schema_version.unwrap_or(1);
+
+        if !(MIN_PAYLOAD_SCHEMA_VERSION..=PAYLOAD_SCHEMA_VERSION).contains(&version) {
+            return Err(PylonError::UnsupportedSchemaVersion(version));
+        }
+
+        Ok(())
+    }
+
+    /// Returns a copy of the payload, stamped with the schema version it's sent in.
+    fn versioned(&self) -> Self {
+        Self {
+            schema_version: Some(PAYLOAD_SCHEMA_VERSION),
+            ..self.clone()
         }
     }
 }
 
+/// A generated wormhole code for a pending sender.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Debug)]
+pub struct CodeInfo {
+    /// The wormhole code for authentication.
+    pub code: String,
+
+    /// The time after which the code expires, if no payload was sent through it.
+    #[schema(value_type = Timestamp)]
+    pub expires_at: SystemTime,
+
+    /// The number of words in the code.
+    pub words: usize,
+
+    /// The entropy of the code's words in bits, ie: how hard it is to guess.
+    pub entropy_bits: f64,
+}
+
+impl CodeInfo {
+    /// Returns the entropy (in bits) of a code with the given number of words.
+    ///
+    /// # Arguments
+    ///
+    /// * `words` - The number of words in the code.
+    pub fn entropy(words: usize) -> f64 {
+        words as f64 * (WORDLIST_SIZE as f64).log2()
+    }
+}
+
+/// The state of a transfer, as seen by the sender.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferState {
+    /// The code was generated, and the sender is waiting for a receiver.
+    Pending,
+
+    /// The receiver connected and the handshake completed.
+    Connected,
+
+    /// The payload was delivered to the receiver.
+    Delivered,
+
+    /// The transfer failed.
+    Failed,
+
+    /// The code expired before a receiver connected.
+    Expired,
+
+    /// The sender cancelled the transfer before a receiver connected.
+    Cancelled,
+}
+
+/// The status of a transfer, as reported to the sender.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Debug)]
+pub struct TransferStatus {
+    /// The wormhole code of the transfer.
+    pub code: String,
+
+    /// The current state of the transfer.
+    pub state: TransferState,
+
+    /// The reason the transfer failed (only populated in the failed state).
+    pub error: Option<String>,
+
+    /// The time of the last state change.
+    #[schema(value_type = Timestamp)]
+    pub updated_at: SystemTime,
+}
+
+/// The outcome of a health check.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckState {
+    /// The check passed.
+    Ok,
+
+    /// The check failed.
+    Failed,
+}
+
+/// The result of a single health check.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Debug)]
+pub struct HealthCheck {
+    /// The outcome of the check.
+    pub state: CheckState,
+
+    /// Time (in milliseconds) the check took.
+    pub duration_ms: u64,
+
+    /// The reason the check failed (only populated in the failed state).
+    pub error: Option<String>,
+}
+
+/// The health of the service, as reported to liveness and readiness probes.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Debug)]
+pub struct HealthReport {
+    /// The overall outcome, which is only `ok` if every check passed.
+    pub state: CheckState,
+
+    /// The individual checks, keyed by name.
+    pub checks: BTreeMap<String, HealthCheck>,
+}
+
+impl HealthReport {
+    /// Creates a report from the results of its checks.
+    ///
+    /// # Arguments
+    ///
+    /// * `checks` - The individual checks, keyed by name.
+    pub fn new(checks: BTreeMap<String, HealthCheck>) -> Self {
+        let state = if checks.values().all(|check| check.state == CheckState::Ok) {
+            CheckState::Ok
+        } else {
+            CheckState::Failed
+        };
+
+        Self { state, checks }
+    }
+
+    /// Checks whether every check passed.
+    pub fn is_ok(&self) -> bool {
+        self.state == CheckState::Ok
+    }
+}
+
+/// An event in the lifecycle of a transfer.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TransferEvent {
+    /// A mailbox was allocated on the rendezvous server for the sender.
+    MailboxAllocated,
+
+    /// The peer connected to the mailbox.
+    PeerConnected,
+
+    /// Both peers derived the same key from the code.
+    KeyConfirmed,
+
+    /// The payload was sent to the receiver.
+    PayloadSent,
+
+    /// The payload was received from the sender.
+    PayloadReceived,
+
+    /// The code expired before a receiver connected.
+    Expired,
+
+    /// The sender cancelled the transfer.
+    Cancelled,
+
+    /// The transfer failed.
+    Error {
+        /// The reason of the failure.
+        message: String,
+    },
+}
+
+impl TransferEvent {
+    /// Returns the name of the event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MailboxAllocated => "mailbox_allocated",
+            Self::PeerConnected => "peer_connected",
+            Self::KeyConfirmed => "key_confirmed",
+            Self::PayloadSent => "payload_sent",
+            Self::PayloadReceived => "payload_received",
+            Self::Expired => "expired",
+            Self::Cancelled => "cancelled",
+            Self::Error { .. } => "error",
+        }
+    }
+
+    /// Returns whether the event ends the lifecycle of the transfer.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            Self::PayloadSent
+                | Self::PayloadReceived
+                | Self::Expired
+                | Self::Cancelled
+                | Self::Error { .. }
+        )
+    }
+}
+
 /// The Pylon mode.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum Mode {
     /// Mode used to send messages.
     Sender,
@@ -90,11 +558,121 @@ pub enum Mode {
 /// The Pylon connection mode (Sender/Receiver).
 #[allow(clippy::large_enum_variant)]
 enum ConnType {
+    /// A sender connection whose mailbox is open, until the handshake with a receiver starts.
+    Pending(PendingGuard),
+
     /// A future sender connection that must be awaited to fully establish the connection.
     FutureConn(FutureConn),
 
-    /// An established receiver connection.
-    EstConn(EstConn),
+    /// An established connection (always the case in Receiver mode).
+    EstConn(WormholeGuard),
+}
+
+/// A sender's connection to the rendezvous server, holding the nameplate and mailbox of its code.
+struct PendingConn {
+    /// The connection to the rendezvous server.
+    server: RendezvousServer,
+
+    /// The application ID.
+    appid: AppID,
+
+    /// The wormhole code (used as the PAKE password).
+    code: String,
+
+    /// The application version, sent to the receiver during the handshake.
+    app_version: AppVersion,
+}
+
+impl PendingConn {
+    /// Starts the client-client handshake, which completes once a receiver connects.
+    fn handshake(self) -> FutureConn {
+        Box::new(Box::pin(Wormhole::connect_custom(
+            self.server,
+            self.appid,
+            self.code,
+            self.app_version,
+        )))
+    }
+
+    /// Releases the nameplate and closes the mailbox, so that the code can't be used anymore.
+    async fn close(self) -> Result<(), PylonError> {
+        // The mood isn't exported by magic-wormhole, so it's deserialized from its wire name.
+        let mood =
+            serde_json::from_str("\"lonely\"").map_err(|e| PylonError::Internal(e.to_string()))?;
+
+        self.server
+            .shutdown(mood)
+            .await
+            .map_err(|e| PylonError::RendezvousUnreachable(e.to_string()))
+    }
+}
+
+/// A pending sender connection, which is closed in the background if it is dropped before the
+/// handshake starts (eg: when the request holding it is aborted).
+struct PendingGuard(Option<PendingConn>);
+
+impl PendingGuard {
+    /// Returns the connection, which the caller is now responsible for closing.
+    fn into_inner(mut self) -> PendingConn {
+        self.0.take().expect("connection is only taken once")
+    }
+
+    /// Releases the nameplate and closes the mailbox, so that the code can't be used anymore.
+    async fn close(self) -> Result<(), PylonError> {
+        self.into_inner().close().await
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if let (Some(conn), Ok(runtime)) = (self.0.take(), Handle::try_current()) {
+            runtime.spawn(async move {
+                let _ = conn.close().await;
+            });
+        }
+    }
+}
+
+/// An established wormhole, which is closed in the background if it is dropped before being closed
+/// explicitly (eg: when an operation on it fails, times out or is aborted).
+struct WormholeGuard(Option<EstConn>);
+
+impl WormholeGuard {
+    /// Returns the wormhole, which the caller is now responsible for closing.
+    fn into_inner(mut self) -> EstConn {
+        self.0.take().expect("wormhole is only taken once")
+    }
+
+    /// Closes the wormhole, releasing its nameplate and mailbox on the rendezvous server.
+    async fn close(self) -> Result<(), PylonError> {
+        self.into_inner().close().await?;
+
+        Ok(())
+    }
+}
+
+impl Deref for WormholeGuard {
+    type Target = EstConn;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().expect("wormhole is only taken once")
+    }
+}
+
+impl DerefMut for WormholeGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut().expect("wormhole is only taken once")
+    }
+}
+
+impl Drop for WormholeGuard {
+    fn drop(&mut self) {
+        if let (Some(wormhole), Ok(runtime)) = (self.0.take(), Handle::try_current()) {
+            runtime.spawn(async move {
+                let _ = wormhole.close().await;
+            });
+        }
+    }
 }
 
 /// An object that can send or receive messages using an encrypted wormhole tunnel.
@@ -103,8 +681,14 @@ pub struct Pylon {
     /// A wormhole connection in either sender or receiver mode.
     conn: ConnType,
 
-    /// The generated wormhole code for PAKE authentication (only populated in Sender mode).
+    /// The Pylon mode (Sender/Receiver).
+    mode: Mode,
+
+    /// The wormhole code for PAKE authentication (generated in Sender mode).
     pub code: Option<String>,
+
+    /// The deadlines of the wormhole operations.
+    timeouts: TimeoutConfig,
 }
 
 impl Pylon {
@@ -114,69 +698,618 @@ impl Pylon {
     ///
     /// * `mode` - The Pylon mode (Sender/Receiver).
     /// * `code` - The wormhole code for PAKE authentication (only required in Receiver mode).
-    pub async fn new(mode: Mode, code: Option<String>) -> Result<Self, ThreadSafeError> {
-        let conf = AppConfig {
-            id: AppID(Cow::from(APP_ID)),
-            rendezvous_url: Cow::from(DEFAULT_RENDEZVOUS_SERVER),
-            app_version: APP_VERSION,
-        };
-
+    /// * `config` - The service configuration (rendezvous server and application ID).
+    pub async fn new(
+        mode: Mode,
+        code: Option<String>,
+        config: &PylonConfig,
+    ) -> Result<Self, PylonError> {
         match mode {
-            Mode::Sender => {
-                let conn = Wormhole::connect_without_code(conf, CODE_LENGTH).await?;
-                let code = conn.0.code;
-
-                Ok(Self {
-                    conn: ConnType::FutureConn(Box::new(Box::pin(conn.1))),
-                    code: Some(code.0),
-                })
-            }
+            Mode::Sender => Self::with_code_length(config.code_length, config).await,
             Mode::Receiver => {
                 if let Some(code) = code {
-                    let conn = Wormhole::connect_with_code(conf, Code(code)).await?;
+                    if !is_valid_code(&code) {
+                        return Err(PylonError::BadCode);
+                    }
+
+                    let conn = Self::connect_with_code(Code(code.clone()), config).await?;
 
                     return Ok(Self {
-                        conn: ConnType::EstConn(conn.1),
-                        code: None,
+                        conn: ConnType::EstConn(WormholeGuard(Some(conn))),
+                        mode,
+                        code: Some(code),
+                        timeouts: config.timeouts,
                     });
                 }
 
-                Err(Box::new(PylonError(
+                Err(PylonError::EmptyPayload(
                     "Wormhole code is required to establish the connection".into(),
-                )))
+                ))
             }
         }
     }
 
+    /// Creates a new Pylon in Sender mode, with a code of the given number of words.
+    ///
+    /// # Arguments
+    ///
+    /// * `code_length` - The number of words in the generated code.
+    /// * `config` - The service configuration (rendezvous server and application ID).
+    pub async fn with_code_length(
+        code_length: usize,
+        config: &PylonConfig,
+    ) -> Result<Self, PylonError> {
+        let app_config = config.app_config();
+        let (server, nameplate) = timeout(config.timeouts.connect(), async {
+            let (mut server, _) =
+                RendezvousServer::connect(&app_config.id, &app_config.rendezvous_url).await?;
+            let (nameplate, _) = server.allocate_claim_open().await?;
+
+            Ok::<_, RendezvousError>((server, nameplate))
+        })
+        .await
+        .map_err(|_| PylonError::Timeout(TimeoutStage::Connect))?
+        .map_err(|e| PylonError::RendezvousUnreachable(e.to_string()))?;
+        let Code(code) = Code::new(&nameplate, &wordlist::choose_words(code_length));
+
+        Ok(Self {
+            conn: ConnType::Pending(PendingGuard(Some(PendingConn {
+                server,
+                appid: app_config.id,
+                code: code.clone(),
+                app_version: app_config.app_version,
+            }))),
+            mode: Mode::Sender,
+            code: Some(code),
+            timeouts: config.timeouts,
+        })
+    }
+
+    /// Connects to the sender of a wormhole code, and performs the client-client handshake.
+    ///
+    /// Unlike [`Wormhole::connect_with_code`], opening the mailbox and waiting for the handshake
+    /// have separate deadlines.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code of the sender.
+    /// * `config` - The service configuration (rendezvous server, application ID and timeouts).
+    async fn connect_with_code(code: Code, config: &PylonConfig) -> Result<EstConn, PylonError> {
+        let app_config = config.app_config();
+        let server = timeout(config.timeouts.connect(), async {
+            let (mut server, _) =
+                RendezvousServer::connect(&app_config.id, &app_config.rendezvous_url).await?;
+            server.claim_open(code.nameplate()).await?;
+
+            Ok::<_, RendezvousError>(server)
+        })
+        .await
+        .map_err(|_| PylonError::Timeout(TimeoutStage::Connect))?
+        .map_err(|e| PylonError::RendezvousUnreachable(e.to_string()))?;
+
+        let conn = timeout(
+            config.timeouts.handshake(),
+            Wormhole::connect_custom(server, app_config.id, code.0, app_config.app_version),
+        )
+        .await
+        .map_err(|_| PylonError::Timeout(TimeoutStage::Handshake))??;
+
+        Ok(conn)
+    }
+
+    /// Closes the Pylon's connection to the rendezvous server.
+    ///
+    /// A pending sender or an established wormhole is closed cleanly, releasing its nameplate and
+    /// mailbox. A handshake in progress can only be abandoned, which drops the connection.
+    pub async fn close(self) -> Result<(), PylonError> {
+        match self.conn {
+            ConnType::Pending(conn) => conn.close().await,
+            ConnType::FutureConn(_) => Ok(()),
+            ConnType::EstConn(conn) => conn.close().await,
+        }
+    }
+
+    /// Performs the client-client handshake with the peer, if it hasn't been performed yet.
+    ///
+    /// In Sender mode, this waits until a receiver connects using the generated code. Calling this is
+    /// optional, since activating the Pylon performs the handshake as well.
+    pub async fn connect(&mut self) -> Result<(), PylonError> {
+        if let ConnType::Pending(_) = self.conn {
+            // The handshake takes over the connection, so the (empty) guard is only a placeholder.
+            self.conn = match mem::replace(&mut self.conn, ConnType::EstConn(WormholeGuard(None))) {
+                ConnType::Pending(conn) => ConnType::FutureConn(conn.into_inner().handshake()),
+                conn => conn,
+            };
+        }
+
+        if let ConnType::FutureConn(conn) = &mut self.conn {
+            let wh = conn.await?;
+            self.conn = ConnType::EstConn(WormholeGuard(Some(wh)));
+        }
+
+        Ok(())
+    }
+
     /// "Activates" the Pylon, and performs a send or a receive operation.
     ///
     /// # Arguments
     ///
     /// * `payload` - The payload to send (only required in Sender mode).
-    pub async fn activate(
-        self,
-        payload: Option<&Payload>,
-    ) -> Result<Option<Payload>, ThreadSafeError> {
-        let payload = payload;
+    pub async fn activate(self, payload: Option<&Payload>) -> Result<Option<Payload>, PylonError> {
+        match self.mode {
+            Mode::Sender => {
+                let payload = payload.ok_or_else(|| {
+                    PylonError::EmptyPayload("Payload cannot be empty in Sender mode".into())
+                })?;
+                let mut wh = self.into_wormhole().await?;
+                wh.send_json(&payload.versioned()).await?;
+
+                // The payload was already handed over, so failing to close doesn't fail the send.
+                let _ = wh.close().await;
+
+                Ok(None)
+            }
+            Mode::Receiver => {
+                let receive_timeout = self.timeouts.receive();
+                let mut wh = self.into_wormhole().await?;
+                let mut payload: Payload =
+                    timeout(receive_timeout, wh.receive_json())
+                        .await
+                        .map_err(|_| PylonError::Timeout(TimeoutStage::Receive))???;
+                let _ = wh.close().await;
+                payload.check_schema_version()?;
+                payload.verify()?;
+
+                Ok(Some(payload))
+            }
+        }
+    }
+
+    /// Turns the Pylon into a persistent chat session, performing the handshake first if required.
+    ///
+    /// Unlike [`Pylon::activate`], the wormhole is kept open so that both peers can exchange any
+    /// number of payloads in both directions, until either of them closes the session.
+    pub async fn into_session(self) -> Result<ChatSession, PylonError> {
+        let code = self.code.clone().unwrap_or_default();
+        let wormhole = self.into_wormhole().await?;
 
+        Ok(ChatSession {
+            wormhole,
+            code,
+            sent: 0,
+            received: 0,
+        })
+    }
+
+    /// Returns the established wormhole, performing the handshake first if required.
+    async fn into_wormhole(self) -> Result<WormholeGuard, PylonError> {
         match self.conn {
-            ConnType::FutureConn(conn) => {
-                if let Some(payload) = payload {
-                    let mut wh = conn.await?;
-                    wh.send_json(&payload).await?;
-
-                    Ok(None)
-                } else {
-                    Err(Box::new(PylonError(
-                        "Payload cannot be empty in Sender mode".into(),
-                    )))
-                }
+            ConnType::Pending(conn) => {
+                Ok(WormholeGuard(Some(conn.into_inner().handshake().await?)))
             }
-            ConnType::EstConn(mut conn) => {
-                let payload = conn.receive_json().await??;
+            ConnType::FutureConn(conn) => Ok(WormholeGuard(Some(conn.await?))),
+            ConnType::EstConn(conn) => Ok(conn),
+        }
+    }
+
+    /// Sends a file through the wormhole, using a transit connection for the file contents.
+    ///
+    /// The file's metadata (name, size and checksum) is sent ahead of the file itself, so that the
+    /// receiver can verify what it received.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The file metadata to send.
+    /// * `file` - The file contents.
+    /// * `config` - The service configuration (transit relay server).
+    pub async fn send_file<F>(
+        self,
+        payload: &Payload,
+        file: &mut F,
+        config: &PylonConfig,
+    ) -> Result<(), PylonError>
+    where
+        F: AsyncRead + Unpin,
+    {
+        let (file_name, size) = match (&payload.file_name, payload.size) {
+            (Some(file_name), Some(size)) => (file_name.clone(), size),
+            _ => {
+                return Err(PylonError::EmptyPayload(
+                    "File name and size are required to send a file".into(),
+                ))
+            }
+        };
+
+        match self.mode {
+            Mode::Sender => {
+                let mut wh = self.into_wormhole().await?;
+                wh.send_json(&payload.versioned()).await?;
+
+                transfer::send_file(
+                    wh.into_inner(),
+                    config.relay_url()?,
+                    file,
+                    file_name,
+                    size,
+                    Abilities::ALL_ABILITIES,
+                    |_, _| {},
+                    future::pending(),
+                )
+                .await?;
+
+                Ok(())
+            }
+            Mode::Receiver => Err(PylonError::Internal(
+                "Files can only be sent in Sender mode".into(),
+            )),
+        }
+    }
+
+    /// Waits for a file offer from the sending peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The service configuration (transit relay server).
+    pub async fn receive_file(self, config: &PylonConfig) -> Result<FileOffer, PylonError> {
+        if self.mode == Mode::Sender {
+            return Err(PylonError::Internal(
+                "Files can only be received in Receiver mode".into(),
+            ));
+        }
+
+        match self.receive(config).await? {
+            Received::File(offer) => Ok(offer),
+            Received::Message(_) => Err(PylonError::InvalidPayload(
+                "Received payload is not a file".into(),
+            )),
+        }
+    }
+
+    /// Waits for whatever the sending peer sends, be it a message or a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The service configuration (transit relay server).
+    pub async fn receive(self, config: &PylonConfig) -> Result<Received, PylonError> {
+        if self.mode == Mode::Sender {
+            return Err(PylonError::Internal(
+                "Payloads can only be received in Receiver mode".into(),
+            ));
+        }
+
+        let receive_timeout = self.timeouts.receive();
+        let mut conn = self.into_wormhole().await?;
+        let mut payload: Payload = timeout(receive_timeout, conn.receive_json())
+            .await
+            .map_err(|_| PylonError::Timeout(TimeoutStage::Receive))???;
+
+        if let Err(e) = payload.check_schema_version() {
+            let _ = conn.close().await;
+            return Err(e);
+        }
+
+        if payload.file_name.is_some() {
+            return Ok(Received::File(
+                FileOffer::request(conn, payload, config).await?,
+            ));
+        }
+
+        let _ = conn.close().await;
+        payload.verify()?;
+
+        Ok(Received::Message(payload))
+    }
+}
+
+/// Something received from the sending peer.
+#[allow(clippy::large_enum_variant)]
+pub enum Received {
+    /// A (verified) message.
+    Message(Payload),
+
+    /// A file, pending acceptance.
+    File(FileOffer),
+}
+
+/// A frame exchanged between the peers of a chat session.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
+enum ChatFrame {
+    /// A chat message.
+    Message(Payload),
+
+    /// The peer closed the session.
+    Close,
+}
+
+/// A persistent chat session over an established wormhole.
+///
+/// Every payload sent through the session is numbered, so that the receiving peer can detect lost
+/// or reordered messages.
+pub struct ChatSession {
+    /// The established wormhole.
+    wormhole: WormholeGuard,
+
+    /// The wormhole code of the session.
+    pub code: String,
+
+    /// The number of payloads sent so far.
+    sent: u64,
+
+    /// The number of payloads received so far.
+    received: u64,
+}
+
+impl ChatSession {
+    /// Sends a message to the peer, and returns the payload that was sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to send.
+    pub async fn send(&mut self, message: &str) -> Result<Payload, PylonError> {
+        self.send_message(message, None).await
+    }
+
+    /// Sends a message encrypted by the client to the peer, and returns the payload that was sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The (base64-encoded) ciphertext to send.
+    /// * `encryption` - How the message was encrypted.
+    pub async fn send_encrypted(
+        &mut self,
+        message: &str,
+        encryption: Encryption,
+    ) -> Result<Payload, PylonError> {
+        self.send_message(message, Some(encryption)).await
+    }
+
+    /// Sends a message to the peer, and returns the payload that was sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to send.
+    /// * `encryption` - How the message was encrypted, if it was.
+    async fn send_message(
+        &mut self,
+        message: &str,
+        encryption: Option<Encryption>,
+    ) -> Result<Payload, PylonError> {
+        if message.is_empty() {
+            return Err(PylonError::EmptyPayload("Message cannot be empty".into()));
+        }
+
+        let payload = Payload {
+            seq: Some(self.sent),
+            encryption,
+            ..Payload::from((message, self.code.as_str())).versioned()
+        };
+        crypto::validate(&payload)?;
+        self.wormhole
+            .send_json(&ChatFrame::Message(payload.clone()))
+            .await?;
+        self.sent += 1;
+
+        Ok(payload)
+    }
+
+    /// Waits for the next payload from the peer.
+    ///
+    /// Returns `None` once the peer has closed the session.
+    pub async fn receive(&mut self) -> Result<Option<Payload>, PylonError> {
+        match self.wormhole.receive_json().await?? {
+            ChatFrame::Message(mut payload) => {
+                payload.check_schema_version()?;
+                payload.verify()?;
+
+                if payload.seq != Some(self.received) {
+                    return Err(PylonError::InvalidPayload(format!(
+                        "Expected message {} from peer, got {:?}",
+                        self.received, payload.seq
+                    )));
+                }
+                self.received += 1;
 
                 Ok(Some(payload))
             }
+            ChatFrame::Close => Ok(None),
+        }
+    }
+
+    /// Notifies the peer that the session is over, and closes the wormhole.
+    pub async fn close(mut self) -> Result<(), PylonError> {
+        self.wormhole.send_json(&ChatFrame::Close).await?;
+        self.wormhole.close().await?;
+
+        Ok(())
+    }
+}
+
+/// A file offered by the sending peer, pending acceptance.
+pub struct FileOffer {
+    /// The file metadata announced by the sender.
+    pub payload: Payload,
+
+    /// The underlying file transfer request.
+    request: ReceiveRequest,
+}
+
+impl FileOffer {
+    /// Requests the file announced by the sending peer, and checks that its size matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The established wormhole.
+    /// * `payload` - The file metadata announced by the sender.
+    /// * `config` - The service configuration (transit relay server).
+    async fn request(
+        conn: WormholeGuard,
+        payload: Payload,
+        config: &PylonConfig,
+    ) -> Result<Self, PylonError> {
+        if payload.file_name.is_none() || payload.size.is_none() {
+            return Err(PylonError::InvalidPayload(
+                "Received payload is not a file".into(),
+            ));
+        }
+
+        let request = transfer::request_file(
+            conn.into_inner(),
+            config.relay_url()?,
+            Abilities::ALL_ABILITIES,
+            future::pending(),
+        )
+        .await?
+        .ok_or_else(|| PylonError::Internal("File transfer was cancelled".into()))?;
+
+        if Some(request.filesize) != payload.size {
+            request.reject().await?;
+
+            return Err(PylonError::InvalidPayload(
+                "Offered file size does not match the announced size".into(),
+            ));
+        }
+
+        Ok(Self { payload, request })
+    }
+
+    /// Accepts the offer and writes the file contents to `writer` as they arrive.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The destination of the file contents.
+    pub async fn accept<W>(self, writer: &mut W) -> Result<(), PylonError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.request
+            .accept(|_, _| {}, writer, future::pending())
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// A reader that verifies the size and SHA256 checksum of the data read through it.
+///
+/// Once the inner reader is exhausted, an error is returned instead of EOF if the data does not match
+/// the expected size or checksum.
+pub struct ChecksumReader<R> {
+    /// The wrapped reader.
+    inner: R,
+
+    /// The expected size in bytes.
+    size: u64,
+
+    /// The expected (hex-encoded) SHA256 checksum.
+    checksum: String,
+
+    /// The number of bytes read so far.
+    read: u64,
+
+    /// The running checksum of the bytes read so far.
+    hasher: Sha256,
+}
+
+impl<R> ChecksumReader<R> {
+    /// Creates a new ChecksumReader.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - The reader to wrap.
+    /// * `size` - The expected size in bytes.
+    /// * `checksum` - The expected (hex-encoded) SHA256 checksum.
+    pub fn new(inner: R, size: u64, checksum: String) -> Self {
+        Self {
+            inner,
+            size,
+            checksum,
+            read: 0,
+            hasher: Sha256::new(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ChecksumReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(n)) => n,
+            other => return other,
+        };
+
+        if n > 0 {
+            this.read += n as u64;
+            this.hasher.update(&buf[..n]);
+
+            if this.read > this.size {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "received more data than announced by the sender",
+                )));
+            }
+
+            return Poll::Ready(Ok(n));
+        }
+
+        if this.read != this.size {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "received {} bytes, but the sender announced {}",
+                    this.read, this.size
+                ),
+            )));
+        }
+
+        let checksum = format!("{:x}", this.hasher.clone().finalize());
+
+        if !checksum.eq_ignore_ascii_case(&this.checksum) {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checksum of the received data does not match the sender's checksum",
+            )));
+        }
+
+        Poll::Ready(Ok(0))
+    }
+}
+
+/// Checks whether the configured rendezvous server can be reached.
+///
+/// Opens (and immediately drops) a connection to the rendezvous server, binding it to the configured
+/// application ID.
+///
+/// # Arguments
+///
+/// * `config` - The service configuration (rendezvous server and application ID).
+pub async fn probe_rendezvous(config: &PylonConfig) -> Result<(), PylonError> {
+    let conf = config.app_config();
+    RendezvousServer::connect(&conf.id, &conf.rendezvous_url)
+        .await
+        .map_err(|e| PylonError::RendezvousUnreachable(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Checks whether a wormhole code is well-formed (`<nameplate>-<password>`, with a numeric
+/// nameplate).
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code to check.
+fn is_valid_code(code: &str) -> bool {
+    match code.split_once('-') {
+        Some((nameplate, password)) => {
+            !nameplate.is_empty()
+                && nameplate.chars().all(|c| c.is_ascii_digit())
+                && !password.is_empty()
         }
+        None => false,
     }
 }
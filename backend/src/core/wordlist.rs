@@ -0,0 +1,53 @@
+//! The PGP word list, used to generate wormhole codes.
+//!
+//! This is the word list used by magic-wormhole (which doesn't export it), so generated codes look
+//! the same as the ones of other wormhole clients. `pgpwords.json` is an unmodified copy of
+//! `src/core/pgpwords.json` from the magic-wormhole 0.4.0 crate
+//! (<https://github.com/magic-wormhole/magic-wormhole.rs>, EUPL-1.2), and is read the same way: each
+//! byte maps to its odd (two syllables) and even (three syllables) word.
+
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+
+use crate::consts::WORDLIST_SIZE;
+
+/// The word list, keyed by byte in hex, each with its odd and even word.
+const PGP_WORDS: &str = include_str!("pgpwords.json");
+
+/// Returns the words used at even and odd positions of a code, in that order.
+fn words() -> &'static [Vec<String>; 2] {
+    static WORDS: OnceLock<[Vec<String>; 2]> = OnceLock::new();
+
+    WORDS.get_or_init(|| {
+        let entries: BTreeMap<String, [String; 2]> =
+            serde_json::from_str(PGP_WORDS).expect("the bundled word list is valid");
+        let mut even = Vec::with_capacity(WORDLIST_SIZE);
+        let mut odd = Vec::with_capacity(WORDLIST_SIZE);
+
+        for [odd_word, even_word] in entries.into_values() {
+            even.push(even_word.to_lowercase());
+            odd.push(odd_word.to_lowercase());
+        }
+
+        [even, odd]
+    })
+}
+
+/// Chooses random words for a wormhole code, alternating between the even and odd words.
+///
+/// # Arguments
+///
+/// * `num_words` - The number of words to choose.
+pub fn choose_words(num_words: usize) -> String {
+    words()
+        .iter()
+        .cycle()
+        .take(num_words)
+        .filter_map(|words| words.choose(&mut OsRng))
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("-")
+}
@@ -0,0 +1,318 @@
+//! Passphrase-based end-to-end encryption of payload messages.
+//!
+//! The wormhole is terminated by the service, which therefore sees every plaintext message. Clients
+//! that don't want that can encrypt their messages before sending them, with XChaCha20-Poly1305 under
+//! a key derived from a passphrase (shared out of band) with Argon2id. The service then only relays
+//! the ciphertext, along with the public parameters the receiver needs to derive the same key.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use serde::{Deserialize, Serialize};
+
+use utoipa::ToSchema;
+
+use unic_segment::Graphemes;
+
+use sha256::digest;
+
+use crate::consts::{
+    KDF_ITERATIONS, KDF_MEMORY_KIB, KDF_PARALLELISM, KDF_SALT_LENGTH, MAX_KDF_ITERATIONS,
+    MAX_KDF_MEMORY_KIB,
+};
+use crate::core::{Payload, PylonError};
+
+/// The only supported cipher.
+pub const CIPHER: &str = "xchacha20poly1305";
+
+/// The only supported key derivation function.
+pub const KDF: &str = "argon2id";
+
+/// Size (in bytes) of XChaCha20-Poly1305 nonces.
+const NONCE_LENGTH: usize = 24;
+
+/// Size (in bytes) of Poly1305 authentication tags.
+const TAG_LENGTH: usize = 16;
+
+/// The parameters of the key derivation, which are public.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Debug)]
+pub struct KdfParams {
+    /// The key derivation function (always `argon2id`).
+    pub algorithm: String,
+
+    /// The random salt (base64-encoded).
+    pub salt: String,
+
+    /// The memory cost (in KiB).
+    pub memory_kib: u32,
+
+    /// The number of passes.
+    pub iterations: u32,
+
+    /// The degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// Creates the default parameters, with a fresh random salt.
+    fn default() -> Self {
+        Self::new(KDF_MEMORY_KIB, KDF_ITERATIONS, KDF_PARALLELISM)
+    }
+}
+
+impl KdfParams {
+    /// Creates key derivation parameters with a fresh random salt.
+    ///
+    /// # Arguments
+    ///
+    /// * `memory_kib` - The memory cost (in KiB).
+    /// * `iterations` - The number of passes.
+    /// * `parallelism` - The degree of parallelism.
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Self {
+        let mut salt = [0u8; KDF_SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+
+        Self {
+            algorithm: KDF.into(),
+            salt: base64::encode(salt),
+            memory_kib,
+            iterations,
+            parallelism,
+        }
+    }
+
+    /// Checks that the parameters are supported, and cheap enough to derive a key with.
+    fn validate(&self) -> Result<Params, PylonError> {
+        if self.algorithm != KDF {
+            return Err(PylonError::InvalidPayload(format!(
+                "unsupported key derivation function '{}'",
+                self.algorithm
+            )));
+        }
+
+        if decode("salt", &self.salt)?.len() < KDF_SALT_LENGTH {
+            return Err(PylonError::InvalidPayload(format!(
+                "salt must be at least {} bytes long",
+                KDF_SALT_LENGTH
+            )));
+        }
+
+        if self.memory_kib > MAX_KDF_MEMORY_KIB || self.iterations > MAX_KDF_ITERATIONS {
+            return Err(PylonError::InvalidPayload(format!(
+                "key derivation may use at most {} KiB and {} passes",
+                MAX_KDF_MEMORY_KIB, MAX_KDF_ITERATIONS
+            )));
+        }
+
+        Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(Key::default().len()),
+        )
+        .map_err(|e| {
+            PylonError::InvalidPayload(format!("invalid key derivation parameters: {}", e))
+        })
+    }
+
+    /// Derives the encryption key from a passphrase.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase shared by the peers.
+    fn derive_key(&self, passphrase: &str) -> Result<Key, PylonError> {
+        let params = self.validate()?;
+        let salt = decode("salt", &self.salt)?;
+        let mut key = Key::default();
+
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| PylonError::Crypto(format!("key derivation failed: {}", e)))?;
+
+        Ok(key)
+    }
+}
+
+/// Describes how a payload message was encrypted.
+#[derive(Serialize, Deserialize, ToSchema, PartialEq, Clone, Debug)]
+pub struct Encryption {
+    /// The cipher (always `xchacha20poly1305`).
+    pub cipher: String,
+
+    /// The random nonce (base64-encoded).
+    pub nonce: String,
+
+    /// The parameters of the key derivation.
+    pub kdf: KdfParams,
+}
+
+impl Encryption {
+    /// Checks that the algorithms are supported and that the parameters are well-formed.
+    ///
+    /// This doesn't require the passphrase, so the service can reject malformed payloads before
+    /// relaying them.
+    pub fn validate(&self) -> Result<(), PylonError> {
+        self.nonce()?;
+        self.kdf.validate()?;
+
+        Ok(())
+    }
+
+    /// Decodes the nonce.
+    fn nonce(&self) -> Result<XNonce, PylonError> {
+        if self.cipher != CIPHER {
+            return Err(PylonError::InvalidPayload(format!(
+                "unsupported cipher '{}'",
+                self.cipher
+            )));
+        }
+
+        let nonce = decode("nonce", &self.nonce)?;
+
+        if nonce.len() != NONCE_LENGTH {
+            return Err(PylonError::InvalidPayload(format!(
+                "nonce must be {} bytes long",
+                NONCE_LENGTH
+            )));
+        }
+
+        Ok(*XNonce::from_slice(&nonce))
+    }
+}
+
+/// Encrypts the message of a payload with the default key derivation parameters.
+///
+/// The returned payload carries the (base64-encoded) ciphertext as its message, with its length
+/// and checksum, along with the encryption parameters.
+///
+/// # Arguments
+///
+/// * `payload` - The payload to encrypt.
+/// * `passphrase` - The passphrase shared by the peers.
+pub fn encrypt(payload: &Payload, passphrase: &str) -> Result<Payload, PylonError> {
+    encrypt_with(payload, passphrase, KdfParams::default())
+}
+
+/// Encrypts the message of a payload with custom key derivation parameters.
+///
+/// # Arguments
+///
+/// * `payload` - The payload to encrypt.
+/// * `passphrase` - The passphrase shared by the peers.
+/// * `kdf` - The key derivation parameters.
+pub fn encrypt_with(
+    payload: &Payload,
+    passphrase: &str,
+    kdf: KdfParams,
+) -> Result<Payload, PylonError> {
+    let message = payload
+        .message
+        .as_deref()
+        .filter(|message| !message.is_empty())
+        .ok_or_else(|| PylonError::EmptyPayload("Message cannot be empty".into()))?;
+
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+    let encryption = Encryption {
+        cipher: CIPHER.into(),
+        nonce: base64::encode(nonce),
+        kdf,
+    };
+
+    let key = encryption.kdf.derive_key(passphrase)?;
+    let ciphertext = XChaCha20Poly1305::new(&key)
+        .encrypt(&encryption.nonce()?, message.as_bytes())
+        .map_err(|_| PylonError::Crypto("encryption failed".into()))?;
+
+    Ok(with_message(
+        payload,
+        base64::encode(ciphertext),
+        Some(encryption),
+    ))
+}
+
+/// Decrypts the message of an encrypted payload.
+///
+/// The returned payload carries the plaintext message, with its length and checksum, and no
+/// encryption parameters.
+///
+/// # Arguments
+///
+/// * `payload` - The encrypted payload.
+/// * `passphrase` - The passphrase shared by the peers.
+pub fn decrypt(payload: &Payload, passphrase: &str) -> Result<Payload, PylonError> {
+    validate(payload)?;
+
+    let encryption = payload
+        .encryption
+        .as_ref()
+        .ok_or_else(|| PylonError::InvalidPayload("payload is not encrypted".into()))?;
+    let ciphertext = decode("message", payload.message.as_deref().unwrap_or_default())?;
+
+    let key = encryption.kdf.derive_key(passphrase)?;
+    let plaintext = XChaCha20Poly1305::new(&key)
+        .decrypt(&encryption.nonce()?, ciphertext.as_slice())
+        .map_err(|_| {
+            PylonError::Crypto("wrong passphrase, or the message was tampered with".into())
+        })?;
+    let message = String::from_utf8(plaintext)
+        .map_err(|_| PylonError::InvalidPayload("decrypted message is not UTF-8".into()))?;
+
+    Ok(with_message(payload, message, None))
+}
+
+/// Checks that an encrypted payload is well-formed, without decrypting it.
+///
+/// Plaintext payloads are always valid.
+///
+/// # Arguments
+///
+/// * `payload` - The payload to check.
+pub fn validate(payload: &Payload) -> Result<(), PylonError> {
+    if let Some(encryption) = &payload.encryption {
+        encryption.validate()?;
+
+        let ciphertext = decode("message", payload.message.as_deref().unwrap_or_default())?;
+
+        if ciphertext.len() <= TAG_LENGTH {
+            return Err(PylonError::InvalidPayload(
+                "encrypted message is too short".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns a copy of a payload with another message, along with its length and checksum.
+///
+/// # Arguments
+///
+/// * `payload` - The original payload.
+/// * `message` - The new message.
+/// * `encryption` - The encryption parameters of the new message.
+fn with_message(payload: &Payload, message: String, encryption: Option<Encryption>) -> Payload {
+    Payload {
+        length: Some(Graphemes::new(&message).count()),
+        checksum: Some(digest(&message)),
+        message: Some(message),
+        encryption,
+        ..payload.clone()
+    }
+}
+
+/// Decodes a base64-encoded value.
+///
+/// # Arguments
+///
+/// * `name` - The name of the value, for error messages.
+/// * `value` - The encoded value.
+fn decode(name: &str, value: &str) -> Result<Vec<u8>, PylonError> {
+    base64::decode(value)
+        .map_err(|e| PylonError::InvalidPayload(format!("invalid {}: {}", name, e)))
+}
@@ -1,9 +1,26 @@
 //! Custom Rocket fairings (middleware).
-use rocket::fairing::{Fairing, Info, Kind};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{self, Fairing, Info, Kind};
 use rocket::http::{Header, Method, Status};
-use rocket::{Request, Response};
+use rocket::shield::Shield;
+use rocket::tokio::select;
+use rocket::tokio::time::{interval, timeout};
+use rocket::{Build, Data, Orbit, Request, Response, Rocket};
+
+use crate::config::{CorsConfig, PylonConfig, SecurityHeadersConfig};
+use crate::consts::RENDEZVOUS_PROBE_TIMEOUT;
+use crate::controllers::TransferTracker;
+use crate::core::{self, TransferEvent, TransferState};
+use crate::metrics::Metrics;
+use crate::ratelimit::RateLimiter;
+use crate::store::SharedStore;
 
 /// Custom fairing that provides CORS middleware functionality.
+///
+/// Applies the CORS policy of the managed service configuration (or the default policy, if none is
+/// managed). Preflight requests themselves are answered by the `preflight` route.
 pub struct CORSFairing;
 
 #[rocket::async_trait]
@@ -16,16 +33,274 @@ impl Fairing for CORSFairing {
     }
 
     async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
-        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+        let default = CorsConfig::default();
+        let cors = request
+            .rocket()
+            .state::<PylonConfig>()
+            .map_or(&default, |config| &config.cors);
+
+        // The response depends on the origin whenever the policy isn't the same for all origins.
+        let any_origin = cors.allowed_origins.iter().all(|origin| origin == "*");
+
+        if !any_origin {
+            response.adjoin_header(Header::new("Vary", "Origin"));
+        }
+
+        let origin = match request.headers().get_one("Origin") {
+            Some(origin) if cors.allows_origin(origin) => origin,
+            _ => return,
+        };
+
+        // The origin is only echoed when the response depends on it, so that shared caches can't
+        // serve a response allowing one origin to another.
+        let allowed_origin = if any_origin { "*" } else { origin };
+
         response.set_header(Header::new(
-            "Access-Control-Allow-Methods",
-            "GET, POST, PATCH, DELETE, OPTIONS",
+            "Access-Control-Allow-Origin",
+            allowed_origin.to_string(),
         ));
-        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
 
-        if response.status() == Status::NotFound && request.method() == Method::Options {
-            response.set_status(Status::NoContent);
+        if cors.allow_credentials {
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
+
+        if !cors.exposed_headers.is_empty() {
+            response.set_header(Header::new(
+                "Access-Control-Expose-Headers",
+                cors.exposed_headers.join(", "),
+            ));
+        }
+
+        let is_preflight = request.method() == Method::Options
+            && request.headers().contains("Access-Control-Request-Method");
+
+        if is_preflight && response.status() == Status::NoContent {
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                cors.allowed_methods.join(", "),
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Allow-Headers",
+                cors.allowed_headers.join(", "),
+            ));
+            response.set_header(Header::new(
+                "Access-Control-Max-Age",
+                cors.max_age.to_string(),
+            ));
+        }
+    }
+}
+
+/// Custom fairing that adds security headers (CSP, HSTS, etc.) to every response.
+///
+/// Applies the security headers of the managed service configuration (or the default ones, if
+/// none is managed). Headers already set by a route are left untouched.
+///
+/// Rocket's default `Shield` is replaced by an empty one, so that its headers don't take precedence
+/// over the configured ones.
+pub struct SecurityHeadersFairing;
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeadersFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security Headers Fairing",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.attach(Shield::new()))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let default = SecurityHeadersConfig::default();
+        let security_headers = request
+            .rocket()
+            .state::<PylonConfig>()
+            .map_or(&default, |config| &config.security_headers);
+
+        for (name, value) in security_headers.headers() {
+            if !response.headers().contains(name) {
+                response.set_header(Header::new(name, value));
+            }
+        }
+    }
+}
+
+/// The time a request was received at, cached on the request by the metrics fairing.
+struct RequestStart(Instant);
+
+/// Custom fairing that records the count and duration of HTTP requests in the managed metrics.
+///
+/// The metrics are created and managed on ignition, unless they already are; launch is aborted if
+/// they cannot be registered.
+///
+/// Requests are labelled by the URI of the route that handled them (or `unmatched`), so that codes
+/// and other path parameters don't end up in label values.
+pub struct MetricsFairing;
+
+#[rocket::async_trait]
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Metrics Fairing",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        if rocket.state::<Metrics>().is_some() {
+            return Ok(rocket);
+        }
+
+        match Metrics::new() {
+            Ok(metrics) => Ok(rocket.manage(metrics)),
+            Err(e) => {
+                error!("Invalid metrics: {}", e);
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let metrics = match request.rocket().state::<Metrics>() {
+            Some(metrics) => metrics,
+            None => return,
+        };
+        let RequestStart(start) = request.local_cache(|| RequestStart(Instant::now()));
+        let route = request
+            .route()
+            .map_or("unmatched", |route| route.uri.as_str());
+
+        metrics.http_request(
+            request.method().as_str(),
+            route,
+            response.status().code,
+            start.elapsed(),
+        );
+    }
+}
+
+/// Custom fairing that loads, validates and manages the service configuration.
+///
+/// Launch is aborted if the configuration is invalid or if the configured rendezvous server cannot
+/// be reached.
+pub struct ConfigFairing;
+
+#[rocket::async_trait]
+impl Fairing for ConfigFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Config Fairing",
+            kind: Kind::Ignite,
         }
     }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = match rocket.figment().extract::<PylonConfig>() {
+            Ok(config) => config,
+            Err(e) => {
+                rocket::config::pretty_print_error(e);
+                return Err(rocket);
+            }
+        };
+
+        if let Err(e) = config.validate() {
+            error!("Invalid configuration: {}", e);
+            return Err(rocket);
+        }
+
+        let probe = timeout(
+            Duration::from_secs(RENDEZVOUS_PROBE_TIMEOUT),
+            core::probe_rendezvous(&config),
+        )
+        .await;
+
+        match probe {
+            Ok(Ok(())) => Ok(rocket.manage(config)),
+            Ok(Err(e)) => {
+                error!(
+                    "Rendezvous server '{}' is unreachable: {}",
+                    config.rendezvous_url, e
+                );
+                Err(rocket)
+            }
+            Err(_) => {
+                error!(
+                    "Rendezvous server '{}' did not respond within {} seconds",
+                    config.rendezvous_url, RENDEZVOUS_PROBE_TIMEOUT
+                );
+                Err(rocket)
+            }
+        }
+    }
+}
+
+/// Custom fairing that periodically evicts expired codes from the session store, and forgets stale
+/// transfer statuses and idle rate limit buckets.
+///
+/// The reaper task is started on liftoff and stops when Rocket shuts down.
+pub struct ReaperFairing;
+
+#[rocket::async_trait]
+impl Fairing for ReaperFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Reaper Fairing",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (store, tracker, config) = match (
+            rocket.state::<SharedStore>(),
+            rocket.state::<TransferTracker>(),
+            rocket.state::<PylonConfig>(),
+        ) {
+            (Some(store), Some(tracker), Some(config)) => {
+                (Arc::clone(store), tracker.clone(), config)
+            }
+            _ => {
+                warn!("Reaper state not managed, expired codes will not be evicted");
+                return;
+            }
+        };
+        let limiter = rocket.state::<RateLimiter>().cloned();
+        let ttl = Duration::from_secs(config.code_ttl);
+        let mut ticker = interval(Duration::from_secs(config.reap_interval));
+        let mut shutdown = rocket.shutdown();
+
+        rocket::tokio::spawn(async move {
+            loop {
+                select! {
+                    _ = ticker.tick() => {
+                        let expired = store.expire().await;
+
+                        if !expired.is_empty() {
+                            info!("Evicted {} expired code(s)", expired.len());
+                        }
+
+                        // Statuses are kept around for a TTL after their last change, so that
+                        // senders can still poll for the outcome.
+                        tracker.prune(ttl).await;
+
+                        if let Some(limiter) = &limiter {
+                            limiter.prune().await;
+                        }
+
+                        for code in &expired {
+                            tracker.update(code, TransferState::Expired, None).await;
+                            tracker.emit(code, TransferEvent::Expired).await;
+                        }
+                    }
+                    _ = &mut shutdown => break,
+                }
+            }
+        });
+    }
 }
@@ -3,21 +3,41 @@
 #[macro_use]
 extern crate rocket;
 
-#[macro_use]
-extern crate lazy_static;
-
 use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rocket::figment::Provider;
+use rocket::fs::FileServer;
+use rocket::{Build, Rocket};
 
 use serde::{Deserialize, Serialize};
 
+use utoipa::ToSchema;
+
+#[cfg(feature = "client")]
+pub mod client;
+pub mod config;
 pub mod consts;
 pub mod controllers;
 pub mod core;
+pub mod crypto;
 pub mod fairings;
+pub mod metrics;
+pub mod openapi;
+pub mod ratelimit;
 pub mod routes;
+pub mod store;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+use crate::consts::API_PREFIX;
+use crate::controllers::TransferTracker;
+use crate::ratelimit::RateLimiter;
+use crate::store::{MemoryStore, SharedStore};
 
 /// A structured API response.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Response<S: Serialize> {
     /// The HTTP status code.
     pub code: u16,
@@ -25,9 +45,62 @@ pub struct Response<S: Serialize> {
     /// An optional message.
     pub message: Option<String>,
 
+    /// A stable, machine-readable error identifier (only populated for errors).
+    #[serde(default)]
+    pub error_code: Option<String>,
+
     /// An optional data payload.
     pub data: Option<S>,
 }
 
 /// A thread-safe error.
 pub type ThreadSafeError = Box<dyn Error + Send + Sync>;
+
+/// Assembles the service: its state, fairings, routes and catchers.
+///
+/// The frontend's static files are served from the configured `static_dir`, if any; otherwise, a
+/// plain index route is mounted at `/`. The service configuration is extracted and validated (and
+/// the rendezvous server probed) on ignition, by [`fairings::ConfigFairing`].
+///
+/// NOTE: Like [`rocket::custom`], this panics if Rocket's own configuration is invalid; check it
+/// with [`config::load`] first to report mistakes instead.
+///
+/// # Arguments
+///
+/// * `provider` - The configuration sources (eg: [`config::figment`]).
+pub fn build<T: Provider>(provider: T) -> Rocket<Build> {
+    let rocket = rocket::custom(provider);
+    let static_dir = rocket
+        .figment()
+        .extract_inner::<Option<PathBuf>>("static_dir")
+        .ok()
+        .flatten();
+
+    let rocket = rocket
+        .manage::<SharedStore>(Arc::new(MemoryStore::default()))
+        .manage(TransferTracker::default())
+        .manage(RateLimiter::default())
+        .attach(fairings::ConfigFairing)
+        .attach(fairings::ReaperFairing)
+        .attach(fairings::CORSFairing)
+        .attach(fairings::MetricsFairing)
+        .attach(fairings::SecurityHeadersFairing)
+        .mount(API_PREFIX, routes::api())
+        .mount("/", routes::monitoring())
+        .mount("/", routes![routes::preflight, routes::openapi])
+        .mount("/", routes::docs())
+        .register(
+            "/",
+            catchers![
+                routes::bad_request,
+                routes::payload_too_large,
+                routes::too_many_requests,
+                routes::unprocessable_entity
+            ],
+        );
+
+    match static_dir {
+        Some(static_dir) => rocket.mount("/", FileServer::from(static_dir)),
+        None => rocket.mount("/", routes![routes::index]),
+    }
+}
@@ -1,71 +1,37 @@
-use pylon_web::fairings;
-use pylon_web::routes;
-
-use rocket::{launch, routes};
-
-#[cfg(not(debug_assertions))]
-use rocket::fs::FileServer;
-
-#[cfg(not(debug_assertions))]
-use rocket::log::LogLevel;
-
-use rocket::Config;
-
-use std::env;
-
-// When run in production (release) mode, we serve the frontend's static files.
-#[cfg(not(debug_assertions))]
-#[launch]
-fn rocket() -> _ {
-    use std::net::{IpAddr, Ipv4Addr};
-
-    let address = match env::var("ROCKET_ADDRESS") {
-        Ok(addr) => addr.parse::<Ipv4Addr>().unwrap(),
-        Err(_) => "127.0.0.1".parse::<Ipv4Addr>().unwrap(),
-    };
-    let address = IpAddr::from(address);
-    let port = match env::var("PORT") {
-        Ok(port) => port.parse::<u16>().expect("could not parse port value"),
-        Err(_) => 8080,
-    };
-    let static_dir =
-        env::var("PYLON_STATIC_DIR").expect("environment variable PYLON_STATIC_DIR not set");
-    rocket::build()
-        .configure(Config {
-            log_level: LogLevel::Normal,
-            address,
-            port,
-            ..Config::release_default()
-        })
-        .attach(fairings::CORSFairing)
-        .mount("/", routes![routes::code, routes::send, routes::receive])
-        .mount("/", FileServer::from(static_dir))
+use pylon_web::config::{self, ConfigArgs};
+
+use clap::Parser;
+
+use std::process::ExitCode;
+
+/// Serves the pylon-web REST API.
+///
+/// Every flag can also be set in `Pylon.toml`, or with a `PYLON_`-prefixed environment variable
+/// (eg: `PYLON_STATIC_DIR`). Timeouts and rate limits are set in the `timeouts` and `rate_limit`
+/// tables (eg: `PYLON_TIMEOUTS='{handshake=30}'`).
+#[derive(Parser)]
+#[command(name = "pylon-web", version)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
 }
 
-// When run in debug mode, we don't serve the frontend.
-#[cfg(debug_assertions)]
-#[launch]
-fn rocket() -> _ {
-    use std::net::{IpAddr, Ipv4Addr};
-
-    let address = match env::var("ROCKET_ADDRESS") {
-        Ok(addr) => addr.parse::<Ipv4Addr>().unwrap(),
-        Err(_) => "127.0.0.1".parse::<Ipv4Addr>().unwrap(),
-    };
-    let address = IpAddr::from(address);
-    let port = match env::var("PORT") {
-        Ok(port) => port.parse::<u16>().expect("could not parse port value"),
-        Err(_) => 8080,
-    };
-    rocket::build()
-        .configure(Config {
-            address,
-            port,
-            ..Config::debug_default()
-        })
-        .attach(fairings::CORSFairing)
-        .mount(
-            "/",
-            routes![routes::index, routes::code, routes::send, routes::receive],
-        )
+#[rocket::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let figment = config::figment(&cli.config);
+
+    // Check the configuration up front, so that mistakes are reported instead of panicking.
+    if let Err(e) = config::load(&figment) {
+        eprintln!("Invalid configuration: {}", e);
+        return ExitCode::from(2);
+    }
+
+    match pylon_web::build(figment).launch().await {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            e.pretty_print();
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,231 @@
+//! Prometheus metrics.
+
+use std::time::Duration;
+
+use prometheus::{
+    exponential_buckets, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::core::{PylonError, TimeoutStage};
+
+/// The direction of a transfer, as seen by the service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// A payload sent by a client.
+    Send,
+
+    /// A payload received by a client.
+    Receive,
+}
+
+impl Direction {
+    /// Returns the metric label of the direction.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Send => "send",
+            Self::Receive => "receive",
+        }
+    }
+}
+
+/// The service metrics, exposed in the Prometheus text format.
+///
+/// Cloning the metrics is cheap, and all clones share the same values.
+#[derive(Clone)]
+pub struct Metrics {
+    /// The registry all metrics are registered with.
+    registry: Registry,
+
+    /// The number of codes generated.
+    codes_generated: IntCounter,
+
+    /// The number of transfers, by direction and outcome (`success`, or the error code).
+    transfers: IntCounterVec,
+
+    /// The time peers take to complete the handshake (for senders, since they started waiting).
+    handshake_duration: Histogram,
+
+    /// The size of transferred payloads (message or file contents), by direction.
+    payload_size: HistogramVec,
+
+    /// The number of pending senders in the session store.
+    pending_codes: IntGauge,
+
+    /// The number of errors talking to the rendezvous server.
+    rendezvous_errors: IntCounter,
+
+    /// The number of HTTP requests handled, by method, route and status.
+    http_requests: IntCounterVec,
+
+    /// The time taken to handle HTTP requests, by method and route.
+    http_duration: HistogramVec,
+}
+
+impl Metrics {
+    /// Creates and registers the service metrics.
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("pylon".into()), None)?;
+
+        let codes_generated = IntCounter::new(
+            "codes_generated_total",
+            "Number of wormhole codes generated",
+        )?;
+        let transfers = IntCounterVec::new(
+            Opts::new(
+                "transfers_total",
+                "Number of transfers, by direction and outcome",
+            ),
+            &["direction", "outcome"],
+        )?;
+        let handshake_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "handshake_duration_seconds",
+                "Time taken by senders and receivers to complete the handshake",
+            )
+            .buckets(exponential_buckets(0.05, 2.0, 10)?),
+        )?;
+        let payload_size = HistogramVec::new(
+            HistogramOpts::new(
+                "payload_size_bytes",
+                "Size of transferred messages and files, by direction",
+            )
+            .buckets(exponential_buckets(64.0, 4.0, 12)?),
+            &["direction"],
+        )?;
+        let pending_codes = IntGauge::new(
+            "pending_codes",
+            "Number of generated codes waiting for a payload",
+        )?;
+        let rendezvous_errors = IntCounter::new(
+            "rendezvous_errors_total",
+            "Number of errors talking to the rendezvous server",
+        )?;
+        let http_requests = IntCounterVec::new(
+            Opts::new(
+                "http_requests_total",
+                "Number of HTTP requests, by method, route and status",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests, by method and route",
+            ),
+            &["method", "route"],
+        )?;
+
+        registry.register(Box::new(codes_generated.clone()))?;
+        registry.register(Box::new(transfers.clone()))?;
+        registry.register(Box::new(handshake_duration.clone()))?;
+        registry.register(Box::new(payload_size.clone()))?;
+        registry.register(Box::new(pending_codes.clone()))?;
+        registry.register(Box::new(rendezvous_errors.clone()))?;
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_duration.clone()))?;
+
+        Ok(Self {
+            registry,
+            codes_generated,
+            transfers,
+            handshake_duration,
+            payload_size,
+            pending_codes,
+            rendezvous_errors,
+            http_requests,
+            http_duration,
+        })
+    }
+
+    /// Records a generated code.
+    pub fn code_generated(&self) {
+        self.codes_generated.inc();
+    }
+
+    /// Records a successful transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the transfer.
+    /// * `size` - The size (in bytes) of the transferred message or file.
+    pub fn transfer_succeeded(&self, direction: Direction, size: u64) {
+        self.transfers
+            .with_label_values(&[direction.label(), "success"])
+            .inc();
+        self.payload_size
+            .with_label_values(&[direction.label()])
+            .observe(size as f64);
+    }
+
+    /// Records a failed transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - The direction of the transfer.
+    /// * `error` - The reason of the failure.
+    pub fn transfer_failed(&self, direction: Direction, error: &PylonError) {
+        self.transfers
+            .with_label_values(&[direction.label(), error.error_code()])
+            .inc();
+        self.error(error);
+    }
+
+    /// Records an error, counting it as a rendezvous server error if applicable.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The error.
+    pub fn error(&self, error: &PylonError) {
+        if let PylonError::RendezvousUnreachable(_) | PylonError::Timeout(TimeoutStage::Connect) =
+            error
+        {
+            self.rendezvous_errors.inc();
+        }
+    }
+
+    /// Records the time a sender or a receiver took to complete the handshake.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - The duration of the handshake.
+    pub fn handshake_completed(&self, duration: Duration) {
+        self.handshake_duration.observe(duration.as_secs_f64());
+    }
+
+    /// Sets the number of pending senders.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - The number of pending senders.
+    pub fn set_pending_codes(&self, count: usize) {
+        self.pending_codes.set(count as i64);
+    }
+
+    /// Records a handled HTTP request.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The request method.
+    /// * `route` - The URI of the route that handled the request.
+    /// * `status` - The response status code.
+    /// * `duration` - The time taken to handle the request.
+    pub fn http_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_duration
+            .with_label_values(&[method, route])
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> Result<String, PylonError> {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .map_err(|e| PylonError::Internal(e.to_string()))?;
+
+        String::from_utf8(buf).map_err(|e| PylonError::Internal(e.to_string()))
+    }
+}
@@ -0,0 +1,85 @@
+//! OpenAPI specification of the REST API, generated from the route and type definitions.
+
+use utoipa::{OpenApi, ToSchema};
+
+use crate::consts::API_PREFIX;
+use crate::core::{
+    CheckState, CodeInfo, HealthCheck, HealthReport, Payload, Timestamp, TransferEvent,
+    TransferState, TransferStatus,
+};
+use crate::crypto::{Encryption, KdfParams};
+use crate::routes;
+
+/// An error response.
+///
+/// Errors are rendered in the same envelope as successful responses, without data.
+#[derive(ToSchema)]
+pub struct ErrorResponse {
+    /// The HTTP status code.
+    pub code: u16,
+
+    /// A human-readable description of the error.
+    pub message: String,
+
+    /// A stable, machine-readable error identifier (eg: `unknown_code`).
+    pub error_code: String,
+}
+
+/// A multipart form used to upload a file to send.
+#[derive(ToSchema)]
+pub struct FileUploadForm {
+    /// The wormhole code generated for the sender.
+    pub code: String,
+
+    /// The file to send.
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+/// The versioned API routes, nested under [`API_PREFIX`] in [`ApiDoc`].
+#[derive(OpenApi)]
+#[openapi(paths(
+    routes::code,
+    routes::cancel_code,
+    routes::send,
+    routes::status,
+    routes::events,
+    routes::chat,
+    routes::receive,
+    routes::send_file,
+    routes::receive_file
+))]
+pub struct ApiV1;
+
+/// The OpenAPI specification of the service.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "pylon-web",
+        description = "Sends and receives messages and files through encrypted wormhole tunnels."
+    ),
+    paths(routes::healthz, routes::readyz, routes::metrics),
+    nest((path = API_PREFIX, api = ApiV1)),
+    components(schemas(
+        Payload,
+        CodeInfo,
+        TransferState,
+        TransferStatus,
+        TransferEvent,
+        CheckState,
+        HealthCheck,
+        HealthReport,
+        Encryption,
+        KdfParams,
+        Timestamp,
+        ErrorResponse,
+        FileUploadForm
+    )),
+    tags(
+        (name = "transfers", description = "Sending and receiving messages"),
+        (name = "files", description = "Sending and receiving files"),
+        (name = "chat", description = "Interactive chat sessions"),
+        (name = "monitoring", description = "Health probes and metrics")
+    )
+)]
+pub struct ApiDoc;
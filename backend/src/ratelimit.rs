@@ -0,0 +1,212 @@
+//! Per-client rate limiting.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::lock::Mutex;
+
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest};
+use rocket::Request;
+
+use crate::config::{PylonConfig, RateBudget, RateLimitConfig};
+
+/// A group of routes that share a rate limit budget.
+pub trait RateScope: Send + Sync + 'static {
+    /// The name of the scope.
+    const NAME: &'static str;
+
+    /// Returns the budget of the scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The rate limit configuration.
+    fn budget(config: &RateLimitConfig) -> RateBudget;
+}
+
+/// The scope of routes that generate codes.
+pub struct CodeScope;
+
+impl RateScope for CodeScope {
+    const NAME: &'static str = "code";
+
+    fn budget(config: &RateLimitConfig) -> RateBudget {
+        config.code
+    }
+}
+
+/// The scope of routes that send payloads.
+pub struct SendScope;
+
+impl RateScope for SendScope {
+    const NAME: &'static str = "send";
+
+    fn budget(config: &RateLimitConfig) -> RateBudget {
+        config.send
+    }
+}
+
+/// The scope of routes that receive payloads.
+pub struct ReceiveScope;
+
+impl RateScope for ReceiveScope {
+    const NAME: &'static str = "receive";
+
+    fn budget(config: &RateLimitConfig) -> RateBudget {
+        config.receive
+    }
+}
+
+/// A token bucket.
+struct Bucket {
+    /// The number of requests that can currently be made.
+    tokens: f64,
+
+    /// The last time the bucket was refilled.
+    refilled: Instant,
+
+    /// The budget the bucket is refilled according to.
+    budget: RateBudget,
+}
+
+impl Bucket {
+    /// Creates a full bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - The budget of the bucket.
+    fn new(budget: RateBudget) -> Self {
+        Self {
+            tokens: budget.burst as f64,
+            refilled: Instant::now(),
+            budget,
+        }
+    }
+
+    /// Returns the number of tokens added per second.
+    fn rate(&self) -> f64 {
+        self.budget.per_minute as f64 / 60.0
+    }
+
+    /// Adds the tokens accumulated since the last refill.
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate()).min(self.budget.burst as f64);
+        self.refilled = now;
+    }
+
+    /// Takes a token from the bucket, or returns how long to wait until one is available.
+    fn take(&mut self) -> Result<(), Duration> {
+        self.refill();
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate()))
+        }
+    }
+
+    /// Checks whether the bucket is full, ie: whether it can be forgotten.
+    fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.budget.burst as f64
+    }
+}
+
+/// Tracks the request budgets of clients, keyed by scope and client IP address.
+///
+/// Cloning the limiter is cheap, and all clones share the same state.
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    /// The token buckets, keyed by scope name and client IP address.
+    buckets: Arc<Mutex<HashMap<(&'static str, IpAddr), Bucket>>>,
+}
+
+impl RateLimiter {
+    /// Takes a request from a client's budget, or returns how long the client has to wait.
+    ///
+    /// # Arguments
+    ///
+    /// * `scope` - The name of the scope of the request.
+    /// * `ip` - The IP address of the client.
+    /// * `budget` - The budget of the scope.
+    pub async fn check(
+        &self,
+        scope: &'static str,
+        ip: IpAddr,
+        budget: RateBudget,
+    ) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().await;
+        buckets
+            .entry((scope, ip))
+            .or_insert_with(|| Bucket::new(budget))
+            .take()
+    }
+
+    /// Forgets clients whose budget is fully replenished.
+    pub async fn prune(&self) {
+        let mut buckets = self.buckets.lock().await;
+        buckets.retain(|_, bucket| !bucket.is_full());
+    }
+}
+
+/// Returns the IP address of the client that made a request.
+///
+/// # Arguments
+///
+/// * `request` - The request.
+/// * `config` - The rate limit configuration.
+pub fn client_ip(request: &Request<'_>, config: &RateLimitConfig) -> IpAddr {
+    config
+        .trusted_proxy_header
+        .as_deref()
+        .and_then(|header| request.headers().get_one(header))
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok())
+        .or_else(|| request.remote().map(|addr| addr.ip()))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// The time a rate limited client has to wait before retrying, cached on the request for the
+/// `429` catcher.
+#[derive(Clone, Copy, Default)]
+pub struct RetryAfter(pub Option<Duration>);
+
+/// A request guard that takes a request from the client's budget for a scope.
+///
+/// Fails with `429 Too Many Requests` once the budget is used up. Always succeeds if rate limiting
+/// is disabled, or if no rate limiter is managed.
+pub struct RateLimit<S: RateScope>(PhantomData<S>);
+
+#[rocket::async_trait]
+impl<'r, S: RateScope> FromRequest<'r> for RateLimit<S> {
+    type Error = Duration;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        let (limiter, config) = match (rocket.state::<RateLimiter>(), rocket.state::<PylonConfig>())
+        {
+            (Some(limiter), Some(config)) if config.rate_limit.enabled => {
+                (limiter, &config.rate_limit)
+            }
+            _ => return Outcome::Success(RateLimit(PhantomData)),
+        };
+
+        let ip = client_ip(request, config);
+
+        match limiter.check(S::NAME, ip, S::budget(config)).await {
+            Ok(()) => Outcome::Success(RateLimit(PhantomData)),
+            Err(retry_after) => {
+                request.local_cache(|| RetryAfter(Some(retry_after)));
+                Outcome::Error((Status::TooManyRequests, retry_after))
+            }
+        }
+    }
+}
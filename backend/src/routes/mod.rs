@@ -1,78 +1,698 @@
 //! API routes definitions and configuration.
 
-use rocket::http::Status;
+use std::path::Path;
+
+use futures::{SinkExt, StreamExt};
+
+use rocket::form::Form;
+use rocket::fs::TempFile;
+use rocket::http::{ContentType, Header, Status};
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest};
 use rocket::response::status::Custom;
+use rocket::response::stream::{Event, EventStream};
+use rocket::response::{self, Responder};
 use rocket::serde::json::Json;
+use rocket::tokio::io::AsyncRead;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Request, Route, Shutdown, State};
+
+use rocket_ws::frame::{CloseCode, CloseFrame};
+use rocket_ws::stream::DuplexStream;
+use rocket_ws::{Channel, Message, WebSocket};
+
+use serde::Serialize;
 
-use crate::controllers;
-use crate::core::Payload;
+use utoipa::OpenApi;
+
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::config::{CorsConfig, PylonConfig};
+use crate::controllers::{self, TransferTracker};
+use crate::core::{
+    ChatSession, CodeInfo, HealthReport, Payload, PylonError, TimeoutStage, TransferEvent,
+    TransferStatus,
+};
+use crate::metrics::Metrics;
+use crate::openapi::{ApiDoc, ErrorResponse, FileUploadForm};
+use crate::ratelimit::{CodeScope, RateLimit, ReceiveScope, RetryAfter, SendScope};
+use crate::store::SharedStore;
 use crate::Response;
 
 /// Type alias for a JSON response with a custom HTTP status.
 type CustomResponse<T> = Custom<Json<Response<T>>>;
 
+/// Type alias for a route result, where errors are rendered as JSON responses.
+type ApiResult<T> = Result<CustomResponse<T>, PylonError>;
+
+/// Wraps data in a successful JSON response.
+///
+/// # Arguments
+///
+/// * `data` - The data payload.
+fn ok<T: Serialize>(data: T) -> CustomResponse<T> {
+    with_status(Status::Ok, data)
+}
+
+/// Wraps data in a JSON response with a custom HTTP status.
+///
+/// # Arguments
+///
+/// * `status` - The HTTP status.
+/// * `data` - The data payload.
+fn with_status<T: Serialize>(status: Status, data: T) -> CustomResponse<T> {
+    Custom(
+        status,
+        Json::from(Response {
+            code: status.code,
+            message: None,
+            error_code: None,
+            data: Some(data),
+        }),
+    )
+}
+
+/// Returns the HTTP status matching a Pylon error.
+///
+/// A peer that doesn't respond in time is reported as a request timeout, while `504 Gateway Timeout`
+/// is kept for the rendezvous server (the service's upstream) not responding in time.
+///
+/// # Arguments
+///
+/// * `error` - The Pylon error.
+pub fn error_status(error: &PylonError) -> Status {
+    match error {
+        PylonError::UnknownCode => Status::NotFound,
+        PylonError::Cancelled => Status::Gone,
+        PylonError::Aborted => Status::ServiceUnavailable,
+        PylonError::BadCode => Status::Forbidden,
+        PylonError::Timeout(TimeoutStage::Connect) => Status::GatewayTimeout,
+        PylonError::Timeout(_) => Status::RequestTimeout,
+        PylonError::RendezvousUnreachable(_) => Status::BadGateway,
+        PylonError::EmptyPayload(_)
+        | PylonError::InvalidPayload(_)
+        | PylonError::InvalidCodeLength(_)
+        | PylonError::Crypto(_) => Status::BadRequest,
+        PylonError::PayloadTooLarge => Status::PayloadTooLarge,
+        PylonError::Integrity(_) | PylonError::UnsupportedSchemaVersion(_) => {
+            Status::UnprocessableEntity
+        }
+        PylonError::RateLimited(_) => Status::TooManyRequests,
+        PylonError::Internal(_) => Status::InternalServerError,
+    }
+}
+
+/// Builds the JSON error response body for an error.
+///
+/// # Arguments
+///
+/// * `error` - The error to render.
+pub fn error_body(error: &PylonError) -> Response<()> {
+    Response {
+        code: error_status(error).code,
+        message: Some(error.to_string()),
+        error_code: Some(error.error_code().into()),
+        data: None,
+    }
+}
+
+impl<'r> Responder<'r, 'static> for PylonError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let mut response =
+            Custom(error_status(&self), Json::from(error_body(&self))).respond_to(request)?;
+
+        if let PylonError::RateLimited(secs) = self {
+            response.set_header(Header::new("Retry-After", secs.to_string()));
+        }
+
+        Ok(response)
+    }
+}
+
+/// A multipart form used to upload a file to send.
+#[derive(FromForm)]
+pub struct FileUpload<'r> {
+    /// The wormhole code generated for the sender.
+    pub code: String,
+
+    /// The file to send.
+    pub file: TempFile<'r>,
+}
+
+/// A streamed file download, with the file's metadata exposed as response headers.
+pub struct FileDownload<R> {
+    /// The file metadata announced by the sender.
+    payload: Payload,
+
+    /// The file contents.
+    reader: R,
+}
+
+impl<'r, R: AsyncRead + Send + 'r> Responder<'r, 'r> for FileDownload<R> {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'r> {
+        let file_name = self
+            .payload
+            .file_name
+            .as_deref()
+            .map(sanitize_file_name)
+            .unwrap_or_default();
+        let mut response = rocket::Response::build();
+
+        response
+            .header(ContentType::Binary)
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", file_name),
+            ))
+            .streamed_body(self.reader);
+
+        if let Some(size) = self.payload.size {
+            response.header(Header::new("X-File-Size", size.to_string()));
+        }
+
+        if let Some(checksum) = self.payload.checksum {
+            response.header(Header::new("X-Checksum-SHA256", checksum));
+        }
+
+        response.ok()
+    }
+}
+
+/// Strips path components and characters that are unsafe in a `Content-Disposition` header from a
+/// file name.
+fn sanitize_file_name(name: &str) -> String {
+    let name = Path::new(name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+
+    name.chars()
+        .filter(|c| !c.is_control() && !matches!(c, '"' | '\\' | '/'))
+        .collect()
+}
+
+/// A request guard that only succeeds for CORS preflight requests allowed by the CORS policy.
+///
+/// Fails with `403 Forbidden` if the origin, the requested method or any requested header is not
+/// allowed.
+pub struct Preflight;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preflight {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let default = CorsConfig::default();
+        let cors = request
+            .rocket()
+            .state::<PylonConfig>()
+            .map_or(&default, |config| &config.cors);
+        let headers = request.headers();
+
+        let allowed = match (
+            headers.get_one("Origin"),
+            headers.get_one("Access-Control-Request-Method"),
+        ) {
+            (Some(origin), Some(method)) => {
+                cors.allows_origin(origin)
+                    && cors.allows_method(method)
+                    && cors.allows_headers(
+                        headers
+                            .get_one("Access-Control-Request-Headers")
+                            .unwrap_or_default(),
+                    )
+            }
+            _ => false,
+        };
+
+        if allowed {
+            Outcome::Success(Preflight)
+        } else {
+            Outcome::Error((Status::Forbidden, ()))
+        }
+    }
+}
+
+/// A request guard that gathers the managed state a chat session needs, so that it can outlive the
+/// request (cloning it is cheap).
+///
+/// Fails with `500 Internal Server Error` if any of it is not managed.
+pub struct ChatState {
+    /// The session store for pending senders.
+    store: SharedStore,
+
+    /// The transfer state tracker.
+    tracker: TransferTracker,
+
+    /// The service metrics.
+    metrics: Metrics,
+
+    /// The service configuration.
+    config: PylonConfig,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ChatState {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+
+        match (
+            rocket.state::<SharedStore>(),
+            rocket.state::<TransferTracker>(),
+            rocket.state::<Metrics>(),
+            rocket.state::<PylonConfig>(),
+        ) {
+            (Some(store), Some(tracker), Some(metrics), Some(config)) => {
+                Outcome::Success(ChatState {
+                    store: store.clone(),
+                    tracker: tracker.clone(),
+                    metrics: metrics.clone(),
+                    config: config.clone(),
+                })
+            }
+            _ => Outcome::Error((Status::InternalServerError, ())),
+        }
+    }
+}
+
 /// Generic index route that indicates whether the service is up and running.
 ///
-/// NOTE: Only available in the debug profile.
-#[cfg(debug_assertions)]
+/// NOTE: Only mounted when the frontend's static files aren't served.
 #[get("/")]
 pub fn index() -> &'static str {
     "Hello, world!"
 }
 
-/// Generates and returns the wormhole authentication code.
-#[get("/code")]
-pub async fn code() -> CustomResponse<String> {
-    let code = controllers::gen_code().await;
+/// Reports whether the service is alive.
+#[utoipa::path(
+    tag = "monitoring",
+    responses((status = 200, description = "The service is alive", body = Response<HealthReport>))
+)]
+#[get("/healthz")]
+pub fn healthz() -> ApiResult<HealthReport> {
+    Ok(ok(controllers::health()))
+}
 
-    match code {
-        Ok(code) => Custom(
-            Status::Ok,
-            Json::from(Response {
-                code: Status::Ok.code,
-                message: None,
-                data: Some(code),
-            }),
-        ),
-        Err(e) => Custom(
-            Status::InternalServerError,
-            Json::from(Response {
-                code: Status::InternalServerError.code,
-                message: Some(e.to_string()),
-                data: None,
-            }),
-        ),
-    }
+/// Reports whether the service is ready to handle transfers, ie: whether the rendezvous server and
+/// the session store can be reached.
+///
+/// Responds with `503 Service Unavailable` if any check failed.
+#[utoipa::path(
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "The service is ready", body = Response<HealthReport>),
+        (status = 503, description = "A check failed", body = Response<HealthReport>)
+    )
+)]
+#[get("/readyz")]
+pub async fn readyz(
+    store: &State<SharedStore>,
+    config: &State<PylonConfig>,
+) -> ApiResult<HealthReport> {
+    let report = controllers::readiness(store.as_ref(), config).await;
+    let status = if report.is_ok() {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    Ok(with_status(status, report))
+}
+
+/// Answers CORS preflight requests for any path.
+///
+/// The CORS headers themselves are added by the CORS fairing. Explicit `OPTIONS` routes take
+/// precedence over this one.
+#[options("/<_..>", rank = 100)]
+pub fn preflight(_preflight: Preflight) -> Status {
+    Status::NoContent
+}
+
+/// Generates and returns the wormhole authentication code, along with its expiry time and entropy.
+///
+/// # Arguments
+///
+/// * `words` - The number of words in the code (optional). Defaults to the configured code length.
+#[utoipa::path(
+    tag = "transfers",
+    params(("words" = Option<usize>, Query, description = "The number of words in the code")),
+    responses(
+        (status = 200, description = "The generated code", body = Response<CodeInfo>),
+        (status = 400, description = "The number of words is out of bounds", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 502, description = "The rendezvous server is unreachable", body = ErrorResponse),
+        (status = 504, description = "The rendezvous server did not respond in time", body = ErrorResponse)
+    )
+)]
+#[get("/code?<words>")]
+pub async fn code(
+    words: Option<&str>,
+    _limit: RateLimit<CodeScope>,
+    store: &State<SharedStore>,
+    tracker: &State<TransferTracker>,
+    metrics: &State<Metrics>,
+    config: &State<PylonConfig>,
+) -> ApiResult<CodeInfo> {
+    let words = words
+        .map(|words| {
+            words.parse().map_err(|_| {
+                PylonError::InvalidCodeLength(format!("'{}' is not a number of words", words))
+            })
+        })
+        .transpose()?;
+    let code = controllers::gen_code(words, store.as_ref(), tracker, metrics, config).await?;
+
+    Ok(ok(code))
 }
 
-/// Sends a payload through the encrypted wormhole tunnel.
+/// Cancels a generated code that no payload was sent through yet.
+///
+/// Receivers waiting on the code are answered with a `cancelled` error.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code to cancel.
+#[utoipa::path(
+    tag = "transfers",
+    params(("code" = String, Path, description = "The wormhole code to cancel")),
+    responses(
+        (status = 200, description = "The code was cancelled", body = Response<TransferStatus>),
+        (status = 404, description = "No pending sender exists for the code", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse)
+    )
+)]
+#[delete("/code/<code>")]
+pub async fn cancel_code(
+    code: &str,
+    _limit: RateLimit<CodeScope>,
+    store: &State<SharedStore>,
+    tracker: &State<TransferTracker>,
+) -> ApiResult<TransferStatus> {
+    let status = controllers::cancel_code(code, store.as_ref(), tracker).await?;
+
+    Ok(ok(status))
+}
+
+/// Enqueues a payload to be sent through the encrypted wormhole tunnel.
+///
+/// Responds with `202 Accepted` as soon as the transfer is queued. Its progress can be polled through
+/// the `/status/<code>` route.
 ///
 /// # Arguments
 ///
 /// * `payload` - The json payload containing the wormhole code and message to send.
+#[utoipa::path(
+    tag = "transfers",
+    request_body = Payload,
+    responses(
+        (status = 202, description = "The transfer was queued", body = Response<Payload>),
+        (status = 400, description = "The payload is invalid", body = ErrorResponse),
+        (status = 404, description = "No pending sender exists for the code", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse)
+    )
+)]
 #[post("/send", data = "<payload>", format = "json")]
-pub async fn send(payload: Json<Payload>) -> CustomResponse<Payload> {
+pub async fn send(
+    _limit: RateLimit<SendScope>,
+    payload: Json<Payload>,
+    store: &State<SharedStore>,
+    tracker: &State<TransferTracker>,
+    metrics: &State<Metrics>,
+    config: &State<PylonConfig>,
+    shutdown: Shutdown,
+) -> ApiResult<Payload> {
     let payload = Json::into_inner(payload);
-    let res = controllers::send_payload(payload).await;
-
-    match res {
-        Ok(payload) => Custom(
-            Status::Ok,
-            Json::from(Response {
-                code: Status::Ok.code,
-                message: None,
-                data: Some(payload),
-            }),
-        ),
-        Err(e) => Custom(
-            Status::InternalServerError,
-            Json::from(Response {
-                code: Status::InternalServerError.code,
-                message: Some(e.to_string()),
-                data: None,
-            }),
-        ),
+    let payload =
+        controllers::send_payload(payload, store.as_ref(), tracker, metrics, config, shutdown)
+            .await?;
+
+    Ok(with_status(Status::Accepted, payload))
+}
+
+/// Returns the status of a transfer.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code of the transfer.
+#[utoipa::path(
+    tag = "transfers",
+    params(("code" = String, Path, description = "The wormhole code of the transfer")),
+    responses(
+        (status = 200, description = "The status of the transfer", body = Response<TransferStatus>),
+        (status = 404, description = "The transfer is unknown", body = ErrorResponse)
+    )
+)]
+#[get("/status/<code>")]
+pub async fn status(code: &str, tracker: &State<TransferTracker>) -> ApiResult<TransferStatus> {
+    let status = controllers::transfer_status(code, tracker).await?;
+
+    Ok(ok(status))
+}
+
+/// Streams the lifecycle events of a transfer as Server-Sent Events.
+///
+/// The current status of the transfer (if known) is sent first as a `status` event, followed by
+/// the lifecycle events published so far, then by the lifecycle events as they happen. The stream
+/// ends once the payload is sent or received, once the transfer fails, expires or is cancelled, or
+/// when the server shuts down.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code of the transfer.
+#[utoipa::path(
+    tag = "transfers",
+    params(("code" = String, Path, description = "The wormhole code of the transfer")),
+    responses((
+        status = 200,
+        description = "A stream of `status` and lifecycle events, named after their `event` field",
+        content_type = "text/event-stream",
+        body = TransferEvent
+    ))
+)]
+#[get("/events/<code>")]
+pub async fn events(
+    code: String,
+    tracker: &State<TransferTracker>,
+    mut end: Shutdown,
+) -> EventStream![] {
+    let tracker = tracker.inner().clone();
+    let (history, mut rx) = tracker.subscribe_with_history(&code).await;
+
+    EventStream! {
+        if let Ok(status) = controllers::transfer_status(&code, &tracker).await {
+            yield Event::json(&status).event("status");
+        }
+
+        for event in history {
+            yield Event::json(&event).event(event.name());
+
+            if event.is_final() {
+                return;
+            }
+        }
+
+        loop {
+            let event = select! {
+                event = rx.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut end => break,
+            };
+
+            yield Event::json(&event).event(event.name());
+
+            if event.is_final() {
+                break;
+            }
+        }
+    }
+}
+
+/// Opens an interactive chat session with a peer over a WebSocket.
+///
+/// Every text message from the client must be a json payload, whose message is sent to the peer.
+/// Every payload received from the peer is forwarded to the client as json. Invalid messages from
+/// the client are answered with a json error response, and any other error is sent the same way
+/// before the WebSocket is closed.
+///
+/// Closing the WebSocket closes the session for both peers (or abandons it, if no peer joined yet),
+/// and the WebSocket is closed once the peer closes the session.
+///
+/// # Arguments
+///
+/// * `code` - The wormhole code of the session. Codes generated by `/code` open the session as the
+///   sender, any other code joins it as the receiver.
+#[utoipa::path(
+    tag = "chat",
+    params(("code" = String, Path, description = "The wormhole code of the session")),
+    responses(
+        (status = 101, description = "The WebSocket was opened; payloads are exchanged as json text messages"),
+        (status = 429, description = "Too many requests", body = ErrorResponse)
+    )
+)]
+#[get("/chat/<code>")]
+pub fn chat(
+    _limit: RateLimit<ReceiveScope>,
+    code: String,
+    ws: WebSocket,
+    state: ChatState,
+    shutdown: Shutdown,
+) -> Channel<'static> {
+    let ChatState {
+        store,
+        tracker,
+        metrics,
+        config,
+    } = state;
+
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let mut early = Vec::new();
+            let abort = async {
+                select! {
+                    _ = shutdown.clone() => {}
+                    _ = disconnected(&mut stream, &mut early) => {}
+                }
+            };
+            let session =
+                controllers::open_chat(code, store.as_ref(), &tracker, &metrics, &config, abort)
+                    .await;
+            let res = match session {
+                Ok(session) => relay_chat(&mut stream, session, early, shutdown).await,
+                Err(e) => Err(e),
+            };
+
+            let frame = match res {
+                Ok(()) => CloseFrame {
+                    code: CloseCode::Normal,
+                    reason: "Session closed".into(),
+                },
+                Err(e) => {
+                    let body = serde_json::to_string(&error_body(&e)).unwrap_or_default();
+                    let _ = stream.send(Message::Text(body)).await;
+
+                    CloseFrame {
+                        code: CloseCode::Error,
+                        reason: e.error_code().into(),
+                    }
+                }
+            };
+
+            // The client may already be gone, in which case there's nobody left to notify.
+            let _ = stream.send(Message::Close(Some(frame))).await;
+
+            Ok(())
+        })
+    })
+}
+
+/// Waits for a WebSocket client to disconnect, buffering the text messages it sends meanwhile.
+///
+/// # Arguments
+///
+/// * `stream` - The WebSocket connection to the client.
+/// * `texts` - The buffer of text messages.
+async fn disconnected(stream: &mut DuplexStream, texts: &mut Vec<String>) {
+    loop {
+        match stream.next().await {
+            Some(Ok(Message::Text(text))) => texts.push(text),
+            Some(Ok(
+                Message::Binary(_) | Message::Ping(_) | Message::Pong(_) | Message::Frame(_),
+            )) => {}
+            Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+        }
+    }
+}
+
+/// Sends the payload of a client's text message to the peer of a chat session.
+///
+/// Returns whether the client is still connected.
+///
+/// # Arguments
+///
+/// * `stream` - The WebSocket connection to the client.
+/// * `session` - The chat session with the peer.
+/// * `text` - The text message, a json payload.
+async fn relay_text(
+    stream: &mut DuplexStream,
+    session: &mut ChatSession,
+    text: &str,
+) -> Result<bool, PylonError> {
+    let sent = match serde_json::from_str::<Payload>(text) {
+        Ok(payload) => {
+            let message = payload.message.unwrap_or_default();
+
+            match payload.encryption {
+                Some(encryption) => session.send_encrypted(&message, encryption).await,
+                None => session.send(&message).await,
+            }
+        }
+        Err(e) => Err(e.into()),
+    };
+
+    match sent {
+        Ok(_) => Ok(true),
+        Err(e @ (PylonError::EmptyPayload(_) | PylonError::InvalidPayload(_))) => {
+            let body = serde_json::to_string(&error_body(&e))?;
+
+            Ok(stream.send(Message::Text(body)).await.is_ok())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Relays payloads between a WebSocket client and a chat session, until either side closes.
+///
+/// # Arguments
+///
+/// * `stream` - The WebSocket connection to the client.
+/// * `session` - The chat session with the peer.
+/// * `early` - The text messages the client sent before the session was open.
+/// * `shutdown` - The server shutdown signal.
+async fn relay_chat(
+    stream: &mut DuplexStream,
+    mut session: ChatSession,
+    early: Vec<String>,
+    mut shutdown: Shutdown,
+) -> Result<(), PylonError> {
+    for text in early {
+        if !relay_text(stream, &mut session, &text).await? {
+            return session.close().await;
+        }
+    }
+
+    loop {
+        select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if !relay_text(stream, &mut session, &text).await? {
+                        break;
+                    }
+                }
+                // Control frames are handled by the WebSocket implementation itself.
+                Some(Ok(Message::Binary(_) | Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+            },
+            payload = session.receive() => match payload? {
+                Some(payload) => {
+                    let body = serde_json::to_string(&payload)?;
+
+                    if stream.send(Message::Text(body)).await.is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            _ = &mut shutdown => break,
+        }
     }
+
+    session.close().await
 }
 
 /// Receives a payload through the encrypted wormhole tunnel.
@@ -80,27 +700,226 @@ pub async fn send(payload: Json<Payload>) -> CustomResponse<Payload> {
 /// # Arguments
 ///
 /// * `payload` - The json payload containing the wormhole code.
+#[utoipa::path(
+    tag = "transfers",
+    request_body(content = Payload, description = "A payload with the wormhole code to receive from"),
+    responses(
+        (status = 200, description = "The received payload", body = Response<Payload>),
+        (status = 403, description = "The code is invalid", body = ErrorResponse),
+        (status = 408, description = "The peer did not respond in time", body = ErrorResponse),
+        (status = 410, description = "The sender cancelled the transfer", body = ErrorResponse),
+        (status = 422, description = "The payload failed its integrity check", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 502, description = "The rendezvous server is unreachable", body = ErrorResponse),
+        (status = 503, description = "The request was aborted (eg: the server is shutting down)", body = ErrorResponse),
+        (status = 504, description = "The rendezvous server did not respond in time", body = ErrorResponse)
+    )
+)]
 #[post("/receive", data = "<payload>", format = "json")]
-pub async fn receive(payload: Json<Payload>) -> CustomResponse<Payload> {
+pub async fn receive(
+    _limit: RateLimit<ReceiveScope>,
+    payload: Json<Payload>,
+    tracker: &State<TransferTracker>,
+    metrics: &State<Metrics>,
+    config: &State<PylonConfig>,
+    shutdown: Shutdown,
+) -> ApiResult<Payload> {
     let payload = Json::into_inner(payload);
-    let res = controllers::receive_payload(payload.code).await;
-
-    match res {
-        Ok(payload) => Custom(
-            Status::Ok,
-            Json::from(Response {
-                code: Status::Ok.code,
-                message: None,
-                data: Some(payload),
-            }),
-        ),
-        Err(e) => Custom(
-            Status::InternalServerError,
-            Json::from(Response {
-                code: Status::InternalServerError.code,
-                message: Some(e.to_string()),
-                data: None,
-            }),
+    let payload =
+        controllers::receive_payload(payload.code, tracker, metrics, config, shutdown).await?;
+
+    Ok(ok(payload))
+}
+
+/// Sends a file through the encrypted wormhole tunnel.
+///
+/// NOTE: The upload size is bounded by Rocket's `limits.file` and `limits.data-form` settings.
+///
+/// # Arguments
+///
+/// * `upload` - The multipart form containing the wormhole code and the file to send.
+#[utoipa::path(
+    tag = "files",
+    request_body(content = FileUploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The file was sent", body = Response<Payload>),
+        (status = 404, description = "No pending sender exists for the code", body = ErrorResponse),
+        (status = 408, description = "The code expired before a receiver connected", body = ErrorResponse),
+        (status = 413, description = "The file exceeds the upload limit", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 503, description = "The request was aborted (eg: the server is shutting down)", body = ErrorResponse)
+    )
+)]
+#[post("/send/file", data = "<upload>", format = "multipart/form-data")]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_file(
+    _limit: RateLimit<SendScope>,
+    upload: Form<FileUpload<'_>>,
+    store: &State<SharedStore>,
+    tracker: &State<TransferTracker>,
+    metrics: &State<Metrics>,
+    config: &State<PylonConfig>,
+    shutdown: Shutdown,
+) -> ApiResult<Payload> {
+    let upload = Form::into_inner(upload);
+    let file_name = upload
+        .file
+        .raw_name()
+        .map(|name| sanitize_file_name(name.dangerous_unsafe_unsanitized_raw().as_str()))
+        .filter(|name| !name.is_empty())
+        .or_else(|| upload.file.name().map(String::from))
+        .unwrap_or_else(|| "file".into());
+    let path = upload
+        .file
+        .path()
+        .ok_or_else(|| PylonError::EmptyPayload("Uploaded file is not available on disk".into()))?;
+    let payload = controllers::send_file(
+        upload.code,
+        file_name,
+        path,
+        store.as_ref(),
+        tracker,
+        metrics,
+        config,
+        shutdown,
+    )
+    .await?;
+
+    Ok(ok(payload))
+}
+
+/// Receives a file through the encrypted wormhole tunnel and streams it back as a download.
+///
+/// NOTE: The response is sent as soon as the transfer starts, before the file's checksum can be
+/// verified. A mismatch aborts the download, so clients must check that they received
+/// `X-File-Size` bytes matching `X-Checksum-SHA256` before using the file.
+///
+/// # Arguments
+///
+/// * `payload` - The json payload containing the wormhole code.
+#[utoipa::path(
+    tag = "files",
+    request_body(content = Payload, description = "A payload with the wormhole code to receive from"),
+    responses(
+        (
+            status = 200,
+            description = "The file contents, streamed before they're verified: clients must check them against `X-File-Size` and `X-Checksum-SHA256`",
+            content_type = "application/octet-stream",
+            body = Vec<u8>,
+            headers(
+                ("Content-Disposition" = String, description = "The name of the file"),
+                ("X-File-Size" = u64, description = "The size of the file in bytes"),
+                ("X-Checksum-SHA256" = String, description = "The SHA256 checksum of the file")
+            )
         ),
-    }
+        (status = 403, description = "The code is invalid", body = ErrorResponse),
+        (status = 429, description = "Too many requests", body = ErrorResponse),
+        (status = 503, description = "The request was aborted (eg: the server is shutting down)", body = ErrorResponse)
+    )
+)]
+#[post("/receive/file", data = "<payload>", format = "json")]
+pub async fn receive_file(
+    _limit: RateLimit<ReceiveScope>,
+    payload: Json<Payload>,
+    tracker: &State<TransferTracker>,
+    metrics: &State<Metrics>,
+    config: &State<PylonConfig>,
+    shutdown: Shutdown,
+) -> Result<FileDownload<impl AsyncRead + Send>, PylonError> {
+    let payload = Json::into_inner(payload);
+    let (payload, reader) =
+        controllers::receive_file(payload.code, tracker, metrics, config, shutdown).await?;
+
+    Ok(FileDownload { payload, reader })
+}
+
+/// Exposes the service metrics in the Prometheus text format.
+#[utoipa::path(
+    tag = "monitoring",
+    responses((
+        status = 200,
+        description = "The metrics, in the Prometheus text format",
+        content_type = "text/plain",
+        body = String
+    ))
+)]
+#[get("/metrics")]
+pub async fn metrics(
+    store: &State<SharedStore>,
+    metrics: &State<Metrics>,
+) -> Result<(ContentType, String), PylonError> {
+    metrics.set_pending_codes(store.list().await.len());
+    let body = metrics.render()?;
+
+    Ok((
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        body,
+    ))
+}
+
+/// Serves the OpenAPI specification of the API routes.
+#[get("/openapi.json")]
+pub fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Returns the versioned API routes, to be mounted under [`crate::consts::API_PREFIX`].
+pub fn api() -> Vec<Route> {
+    routes![
+        code,
+        cancel_code,
+        send,
+        status,
+        events,
+        chat,
+        receive,
+        send_file,
+        receive_file
+    ]
+}
+
+/// Returns the monitoring routes (health probes and metrics), which are not versioned since probes
+/// and scrapers expect them at the root.
+pub fn monitoring() -> Vec<Route> {
+    routes![healthz, readyz, metrics]
+}
+
+/// Returns the routes of the interactive API documentation (Swagger UI) at `/docs`, which renders
+/// the specification served by the `openapi` route.
+///
+/// The UI's assets are embedded in the binary, and the online spec validator is disabled, so the
+/// docs don't depend on (or leak the specification to) third-party servers.
+pub fn docs() -> Vec<Route> {
+    SwaggerUi::new("/docs/<_..>")
+        .config(Config::new(["/openapi.json"]).validator_url("none"))
+        .into()
+}
+
+/// Renders a malformed request (eg: missing or unparsable body) as a JSON error.
+#[catch(400)]
+pub fn bad_request() -> PylonError {
+    PylonError::InvalidPayload("Malformed request".into())
+}
+
+/// Renders a request body that exceeds the configured limits as a JSON error.
+#[catch(413)]
+pub fn payload_too_large() -> PylonError {
+    PylonError::PayloadTooLarge
+}
+
+/// Renders a rate limited request as a JSON error, with a `Retry-After` header.
+#[catch(429)]
+pub fn too_many_requests(request: &Request<'_>) -> PylonError {
+    let RetryAfter(retry_after) = *request.local_cache(RetryAfter::default);
+
+    // Round up, so that clients retrying right on time find a replenished budget.
+    let secs = retry_after.map_or(1, |wait| wait.as_secs_f64().ceil() as u64);
+
+    PylonError::RateLimited(secs.max(1))
+}
+
+/// Renders a request body that could not be parsed as a JSON error.
+#[catch(422)]
+pub fn unprocessable_entity() -> PylonError {
+    PylonError::InvalidPayload("Request body could not be parsed".into())
 }
@@ -0,0 +1,131 @@
+//! Storage for pending sender sessions.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures::future;
+use futures::lock::Mutex;
+
+use crate::core::{Pylon, PylonError};
+
+/// A shareable, type-erased session store, as managed by Rocket.
+pub type SharedStore = Arc<dyn SessionStore>;
+
+/// Storage for pending sender Pylons, keyed by their wormhole code.
+///
+/// A sender Pylon is inserted when its code is generated, and taken out again once a payload is
+/// sent through it.
+#[rocket::async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Stores a pending sender Pylon under its wormhole code, and returns the time it expires at.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code generated for the Pylon.
+    /// * `pylon` - The sender Pylon.
+    /// * `ttl` - How long the Pylon may be kept in the store.
+    async fn insert(&self, code: String, pylon: Pylon, ttl: Duration) -> SystemTime;
+
+    /// Removes and returns the session stored under a wormhole code, if any.
+    ///
+    /// Expired sessions are never returned, and are left for [`SessionStore::expire`] to close.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The wormhole code to look up.
+    async fn take(&self, code: &str) -> Option<Session>;
+
+    /// Returns the wormhole codes of all stored Pylons.
+    async fn list(&self) -> Vec<String>;
+
+    /// Removes and closes all expired Pylons, and returns their codes.
+    ///
+    /// Closing a Pylon releases the nameplate and closes the mailbox of its code on the rendezvous
+    /// server.
+    async fn expire(&self) -> Vec<String>;
+
+    /// Checks whether the store can be reached.
+    ///
+    /// Stores backed by an external service should override this, since the default
+    /// implementation always succeeds.
+    async fn ping(&self) -> Result<(), PylonError> {
+        Ok(())
+    }
+}
+
+/// A pending sender Pylon, along with its expiry time.
+pub struct Session {
+    /// The sender Pylon.
+    pub pylon: Pylon,
+
+    /// The time the session expires at, ie: the time its code stops being valid.
+    pub expires_at: SystemTime,
+}
+
+impl Session {
+    /// Checks whether the session has expired.
+    fn is_expired(&self) -> bool {
+        SystemTime::now() >= self.expires_at
+    }
+}
+
+/// The default, in-memory session store.
+///
+/// Sessions are only visible to the process that created them.
+#[derive(Default)]
+pub struct MemoryStore {
+    /// The stored sessions, keyed by wormhole code.
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+#[rocket::async_trait]
+impl SessionStore for MemoryStore {
+    async fn insert(&self, code: String, pylon: Pylon, ttl: Duration) -> SystemTime {
+        let expires_at = SystemTime::now() + ttl;
+        let session = Session { pylon, expires_at };
+
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(code, session);
+
+        expires_at
+    }
+
+    async fn take(&self, code: &str) -> Option<Session> {
+        let mut sessions = self.sessions.lock().await;
+
+        match sessions.get(code) {
+            Some(session) if !session.is_expired() => sessions.remove(code),
+            _ => None,
+        }
+    }
+
+    async fn list(&self) -> Vec<String> {
+        let sessions = self.sessions.lock().await;
+        sessions.keys().cloned().collect()
+    }
+
+    async fn expire(&self) -> Vec<String> {
+        let mut sessions = self.sessions.lock().await;
+        let codes: Vec<String> = sessions
+            .iter()
+            .filter(|(_, session)| session.is_expired())
+            .map(|(code, _)| code.clone())
+            .collect();
+        let expired: Vec<Session> = codes
+            .iter()
+            .filter_map(|code| sessions.remove(code))
+            .collect();
+        drop(sessions);
+
+        // The sessions are closed without holding the lock, since it involves the rendezvous server.
+        let closed = expired.into_iter().map(|session| session.pylon.close());
+        for res in future::join_all(closed).await {
+            if let Err(e) = res {
+                warn!("Failed to close an expired code: {}", e);
+            }
+        }
+
+        codes
+    }
+}
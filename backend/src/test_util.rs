@@ -0,0 +1,343 @@
+//! Test support utilities.
+//!
+//! Only available with the `test-util` feature.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+
+use futures::lock::Mutex;
+use futures::{SinkExt, StreamExt};
+
+use rocket::tokio::net::{TcpListener, TcpStream};
+use rocket::tokio::sync::mpsc::{self, UnboundedSender};
+use rocket::tokio::task::JoinHandle;
+
+use serde_json::{json, Value};
+
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::config::PylonConfig;
+
+/// A mailbox shared by the peers of a wormhole.
+#[derive(Default)]
+struct Mailbox {
+    /// Every message added to the mailbox so far, replayed to peers that open it later.
+    messages: Vec<Value>,
+
+    /// The outgoing message queues of the peers that opened the mailbox, keyed by side.
+    listeners: HashMap<String, UnboundedSender<Value>>,
+}
+
+/// A claimed nameplate.
+struct Nameplate {
+    /// The ID of the mailbox the nameplate points to.
+    mailbox: String,
+
+    /// The sides that claimed the nameplate and haven't released it yet.
+    claimers: HashSet<String>,
+}
+
+/// The state of the rendezvous server, shared by all connections.
+#[derive(Default)]
+struct ServerState {
+    /// The claimed nameplates, keyed by nameplate.
+    nameplates: HashMap<String, Nameplate>,
+
+    /// The open mailboxes, keyed by mailbox ID.
+    mailboxes: HashMap<String, Mailbox>,
+
+    /// The ID of the next mailbox to create.
+    next_mailbox: u64,
+
+    /// The moods with which clients closed their mailboxes, in order.
+    moods: Vec<String>,
+}
+
+impl ServerState {
+    /// Returns the lowest nameplate that isn't in use.
+    fn free_nameplate(&self) -> String {
+        (1..)
+            .map(|n: u64| n.to_string())
+            .find(|nameplate| !self.nameplates.contains_key(nameplate))
+            .unwrap_or_default()
+    }
+
+    /// Claims a nameplate for a side, and returns the ID of its mailbox.
+    ///
+    /// # Arguments
+    ///
+    /// * `nameplate` - The nameplate to claim.
+    /// * `side` - The side claiming the nameplate.
+    fn claim(&mut self, nameplate: &str, side: &str) -> String {
+        let next_mailbox = &mut self.next_mailbox;
+        let claimed = self.nameplates.entry(nameplate.into()).or_insert_with(|| {
+            *next_mailbox += 1;
+
+            Nameplate {
+                mailbox: format!("mailbox{}", next_mailbox),
+                claimers: HashSet::new(),
+            }
+        });
+        claimed.claimers.insert(side.into());
+
+        claimed.mailbox.clone()
+    }
+
+    /// Releases a nameplate claimed by a side, freeing it once every claimer released it.
+    ///
+    /// # Arguments
+    ///
+    /// * `nameplate` - The nameplate to release.
+    /// * `side` - The side releasing the nameplate.
+    fn release(&mut self, nameplate: &str, side: &str) {
+        if let Some(claimed) = self.nameplates.get_mut(nameplate) {
+            claimed.claimers.remove(side);
+
+            if claimed.claimers.is_empty() {
+                self.nameplates.remove(nameplate);
+            }
+        }
+    }
+
+    /// Opens a mailbox for a side, and replays the messages it already holds.
+    ///
+    /// # Arguments
+    ///
+    /// * `mailbox` - The ID of the mailbox to open.
+    /// * `side` - The side opening the mailbox.
+    /// * `tx` - The outgoing message queue of the side.
+    fn open(&mut self, mailbox: &str, side: &str, tx: &UnboundedSender<Value>) {
+        let mailbox = self.mailboxes.entry(mailbox.into()).or_default();
+
+        for message in &mailbox.messages {
+            let _ = tx.send(message.clone());
+        }
+        mailbox.listeners.insert(side.into(), tx.clone());
+    }
+
+    /// Adds a message to a mailbox, and delivers it to every side that opened it.
+    ///
+    /// # Arguments
+    ///
+    /// * `mailbox` - The ID of the mailbox.
+    /// * `message` - The message to add.
+    fn add(&mut self, mailbox: &str, message: Value) {
+        if let Some(mailbox) = self.mailboxes.get_mut(mailbox) {
+            for tx in mailbox.listeners.values() {
+                let _ = tx.send(message.clone());
+            }
+            mailbox.messages.push(message);
+        }
+    }
+
+    /// Closes a mailbox for a side, deleting it once every side that opened it closed it.
+    ///
+    /// # Arguments
+    ///
+    /// * `mailbox` - The ID of the mailbox to close.
+    /// * `side` - The side closing the mailbox.
+    fn close(&mut self, mailbox: &str, side: &str) {
+        if let Some(open) = self.mailboxes.get_mut(mailbox) {
+            open.listeners.remove(side);
+
+            if open.listeners.is_empty() {
+                self.mailboxes.remove(mailbox);
+            }
+        }
+    }
+}
+
+/// A minimal, in-process rendezvous (mailbox) server, for running sender/receiver round trips
+/// without network access.
+///
+/// The server listens on an ephemeral port on the loopback interface, and stops when dropped. It
+/// implements just enough of the rendezvous protocol for two wormhole clients to find each other
+/// and exchange messages: there are no permissions, and application IDs are not enforced.
+///
+/// File transfers still require a reachable transit relay server.
+pub struct LocalRendezvous {
+    /// The address the server listens on.
+    addr: SocketAddr,
+
+    /// The server state, shared by all connections.
+    state: Arc<Mutex<ServerState>>,
+
+    /// The task accepting connections.
+    handle: JoinHandle<()>,
+}
+
+impl LocalRendezvous {
+    /// Starts the server on an ephemeral port.
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ServerState::default()));
+
+        let shared = Arc::clone(&state);
+        let handle = rocket::tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                rocket::tokio::spawn(handle_connection(stream, Arc::clone(&shared)));
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// Returns the WebSocket URL of the server.
+    pub fn url(&self) -> String {
+        format!("ws://{}/v1", self.addr)
+    }
+
+    /// Returns the nameplates that are currently claimed, in no particular order.
+    pub async fn nameplates(&self) -> Vec<String> {
+        let state = self.state.lock().await;
+        state.nameplates.keys().cloned().collect()
+    }
+
+    /// Returns the number of mailboxes that are currently open.
+    pub async fn open_mailboxes(&self) -> usize {
+        let state = self.state.lock().await;
+        state.mailboxes.len()
+    }
+
+    /// Returns the moods with which clients closed their mailboxes, in order.
+    pub async fn moods(&self) -> Vec<String> {
+        let state = self.state.lock().await;
+        state.moods.clone()
+    }
+
+    /// Returns the default service configuration, pointed at this server.
+    pub fn config(&self) -> PylonConfig {
+        PylonConfig {
+            rendezvous_url: self.url(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for LocalRendezvous {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Serves a single client connection until it's closed.
+///
+/// # Arguments
+///
+/// * `stream` - The client connection.
+/// * `state` - The server state.
+async fn handle_connection(stream: TcpStream, state: Arc<Mutex<ServerState>>) {
+    let ws = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(_) => return,
+    };
+    let (mut sink, mut stream) = ws.split();
+
+    // Replies and peer messages are funneled through a single queue, so that they reach the client
+    // in the order they were produced.
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let writer = rocket::tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if sink.send(Message::Text(message.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let _ = tx.send(json!({"type": "welcome", "welcome": {}}));
+
+    let mut side = String::new();
+    let mut mailboxes = Vec::new();
+
+    while let Some(Ok(message)) = stream.next().await {
+        let request: Value = match message {
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(request) => request,
+                Err(_) => continue,
+            },
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let field = |name: &str| request[name].as_str().unwrap_or_default().to_string();
+
+        let _ = tx.send(json!({"type": "ack", "id": request["id"]}));
+
+        let mut state = state.lock().await;
+        let reply = match field("type").as_str() {
+            "bind" => {
+                side = field("side");
+                None
+            }
+            "list" => {
+                let nameplates: Vec<Value> = state
+                    .nameplates
+                    .keys()
+                    .map(|nameplate| json!({ "id": nameplate }))
+                    .collect();
+                Some(json!({"type": "nameplates", "nameplates": nameplates}))
+            }
+            "allocate" => {
+                let nameplate = state.free_nameplate();
+                state.claim(&nameplate, &side);
+                Some(json!({"type": "allocated", "nameplate": nameplate}))
+            }
+            "claim" => {
+                let mailbox = state.claim(&field("nameplate"), &side);
+                Some(json!({"type": "claimed", "mailbox": mailbox}))
+            }
+            "release" => {
+                state.release(&field("nameplate"), &side);
+                Some(json!({"type": "released"}))
+            }
+            "open" => {
+                let mailbox = field("mailbox");
+                state.open(&mailbox, &side, &tx);
+                mailboxes.push(mailbox);
+                None
+            }
+            "add" => {
+                if let Some(mailbox) = mailboxes.last() {
+                    let message = json!({
+                        "type": "message",
+                        "side": side,
+                        "phase": request["phase"],
+                        "body": request["body"],
+                        "id": request["id"],
+                    });
+                    state.add(mailbox, message);
+                }
+                None
+            }
+            "close" => {
+                let mailbox = field("mailbox");
+                state.moods.push(field("mood"));
+                state.close(&mailbox, &side);
+                mailboxes.retain(|open| *open != mailbox);
+                Some(json!({"type": "closed"}))
+            }
+            "ping" => Some(json!({"type": "pong", "pong": request["ping"]})),
+            _ => Some(json!({"type": "error", "error": "unknown message type", "orig": request})),
+        };
+
+        if let Some(reply) = reply {
+            let _ = tx.send(reply);
+        }
+    }
+
+    // Peers that disconnect without closing their mailboxes stop receiving messages.
+    let mut state = state.lock().await;
+    for mailbox in &mailboxes {
+        state.close(mailbox, &side);
+    }
+    drop(state);
+
+    drop(tx);
+    let _ = writer.await;
+}
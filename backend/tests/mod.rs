@@ -4,17 +4,46 @@
 mod tests {
     use std::sync::Arc;
 
-    use pylon_web::core::{Mode, Payload, Pylon};
+    use pylon_web::config::PylonConfig;
+    use pylon_web::consts::PAYLOAD_SCHEMA_VERSION;
+    use pylon_web::core::{
+        ChecksumReader, CodeInfo, Mode, Payload, Pylon, PylonError, TransferState, TransferStatus,
+    };
+    use pylon_web::test_util::LocalRendezvous;
     use pylon_web::ThreadSafeError;
 
+    use rocket::local::asynchronous::Client;
+
     use unic_segment::Graphemes;
 
     use sha256::digest;
 
+    /// Builds a local client for the service with the given configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The service configuration.
+    async fn local_client(config: PylonConfig) -> Result<Client, rocket::Error> {
+        use rocket::figment::providers::Serialized;
+        use rocket::figment::Figment;
+        use rocket::log::LogLevel;
+        use rocket::Config;
+
+        // Turn off Rocket's debug logging, since it would pollute the test's output.
+        let figment = Figment::from(Config {
+            log_level: LogLevel::Off,
+            ..Config::debug_default()
+        })
+        .merge(Serialized::defaults(config));
+
+        Client::tracked(pylon_web::build(figment)).await
+    }
+
     /// Tests whether the Pylon can generate a code when run in Sender mode.
     #[tokio::test]
     async fn test_code_gen() -> Result<(), ThreadSafeError> {
-        let pylon = Pylon::new(Mode::Sender, None).await?;
+        let rendezvous = LocalRendezvous::start().await?;
+        let pylon = Pylon::new(Mode::Sender, None, &rendezvous.config()).await?;
 
         if pylon.code.is_none() {
             return Err("Code generation failed".into());
@@ -23,6 +52,54 @@ mod tests {
         Ok(())
     }
 
+    /// Tests that closing a pending sender releases its nameplate and closes its mailbox.
+    #[tokio::test]
+    async fn test_close_pending() -> Result<(), ThreadSafeError> {
+        let rendezvous = LocalRendezvous::start().await?;
+        let pylon = Pylon::new(Mode::Sender, None, &rendezvous.config()).await?;
+        let code = pylon.code.clone().ok_or("Code generation failed")?;
+        let nameplate = code.split('-').next().unwrap_or_default().to_string();
+
+        assert_eq!(code.split('-').count(), 3);
+        assert_eq!(rendezvous.nameplates().await, [nameplate]);
+        assert_eq!(rendezvous.open_mailboxes().await, 1);
+
+        pylon.close().await?;
+
+        assert!(rendezvous.nameplates().await.is_empty());
+        assert_eq!(rendezvous.open_mailboxes().await, 0);
+
+        Ok(())
+    }
+
+    /// Tests that dropping a pending sender releases its nameplate and closes its mailbox.
+    #[tokio::test]
+    async fn test_drop_pending() -> Result<(), ThreadSafeError> {
+        use std::time::Duration;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let pylon = Pylon::new(Mode::Sender, None, &rendezvous.config()).await?;
+
+        assert_eq!(rendezvous.nameplates().await.len(), 1);
+
+        drop(pylon);
+
+        // The connection is closed in the background.
+        for _ in 0..100 {
+            if rendezvous.nameplates().await.is_empty() && rendezvous.open_mailboxes().await == 0 {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        assert!(rendezvous.nameplates().await.is_empty());
+        assert_eq!(rendezvous.open_mailboxes().await, 0);
+        assert_eq!(rendezvous.moods().await, ["lonely"]);
+
+        Ok(())
+    }
+
     /// Tests whether the payload was unmodified (not corrupted) in transit.
     #[tokio::test]
     async fn test_payload_match() -> Result<(), ThreadSafeError> {
@@ -31,8 +108,11 @@ mod tests {
         // Channel to send wormhole code between the sender and receiver threads.
         let (tx, mut rx) = channel::<String>(128);
 
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+
         // Sender pylon.
-        let mut pylon = Pylon::new(Mode::Sender, None).await?;
+        let mut pylon = Pylon::new(Mode::Sender, None, &config).await?;
 
         if let Some(code) = pylon.code.take() {
             // For testing purposes, we can share the entire payload between threads, rather creating a new payload per thread.
@@ -53,13 +133,18 @@ mod tests {
                 let code = rx.recv().await.ok_or("Empty code received on channel")?;
 
                 // Receiver pylon.
-                let pylon = Pylon::new(Mode::Receiver, Some(code)).await?;
-                let received_payload = match pylon.activate(Some(&payload)).await? {
-                    Some(payload) => payload,
-                    None => Default::default(),
-                };
+                let pylon = Pylon::new(Mode::Receiver, Some(code), &config).await?;
+                let received_payload = pylon.activate(Some(&payload)).await?.unwrap_or_default();
 
-                assert_eq!(*payload, received_payload);
+                assert!(received_payload.verified);
+                assert_eq!(
+                    Payload {
+                        verified: true,
+                        schema_version: Some(PAYLOAD_SCHEMA_VERSION),
+                        ..(*payload).clone()
+                    },
+                    received_payload
+                );
 
                 Ok::<(), ThreadSafeError>(())
             });
@@ -73,6 +158,130 @@ mod tests {
         Ok(())
     }
 
+    /// Tests whether both peers of a chat session can exchange numbered payloads until it's closed.
+    #[tokio::test]
+    async fn test_chat_session() -> Result<(), ThreadSafeError> {
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+        let sender = Pylon::new(Mode::Sender, None, &config).await?;
+        let code = sender.code.clone().ok_or("Code generation failed")?;
+
+        // The receiver's handshake only completes once the sender's is driven as well.
+        let (sender, receiver) = tokio::join!(sender.into_session(), async {
+            Pylon::new(Mode::Receiver, Some(code.clone()), &config)
+                .await?
+                .into_session()
+                .await
+        });
+        let (mut sender, mut receiver) = (sender?, receiver?);
+
+        assert_eq!(sender.code, code);
+        assert_eq!(receiver.code, code);
+
+        for (seq, message) in ["Hello", "world"].into_iter().enumerate() {
+            let sent = sender.send(message).await?;
+            let received = receiver.receive().await?.ok_or("Session closed early")?;
+
+            assert!(received.verified);
+            assert_eq!(sent.message, received.message);
+            assert_eq!(received.seq, Some(seq as u64));
+        }
+
+        let reply = receiver.send("Hi").await?;
+
+        assert_eq!(reply.seq, Some(0));
+        assert_eq!(
+            sender.receive().await?.and_then(|payload| payload.message),
+            reply.message
+        );
+
+        sender.close().await?;
+
+        assert_eq!(receiver.receive().await?, None);
+
+        receiver.close().await?;
+
+        Ok(())
+    }
+
+    /// Tests that each stage of a wormhole connection gives up once its timeout elapses.
+    #[tokio::test]
+    async fn test_timeouts() -> Result<(), ThreadSafeError> {
+        use pylon_web::config::TimeoutConfig;
+        use pylon_web::core::TimeoutStage;
+        use pylon_web::routes;
+
+        use rocket::http::Status;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = PylonConfig {
+            timeouts: TimeoutConfig {
+                connect: 1,
+                handshake: 1,
+                receive: 1,
+            },
+            ..rendezvous.config()
+        };
+
+        // The sender's handshake is never driven, so the receiver's can't complete.
+        let sender = Pylon::new(Mode::Sender, None, &config).await?;
+        let code = sender.code.clone().ok_or("Code generation failed")?;
+        let res = Pylon::new(Mode::Receiver, Some(code), &config).await;
+
+        assert!(matches!(
+            res,
+            Err(PylonError::Timeout(TimeoutStage::Handshake))
+        ));
+
+        // The handshake completes, but the sender never sends its payload.
+        let mut sender = Pylon::new(Mode::Sender, None, &config).await?;
+        let code = sender.code.clone().ok_or("Code generation failed")?;
+        let (connected, receiver) = tokio::join!(
+            sender.connect(),
+            Pylon::new(Mode::Receiver, Some(code), &config)
+        );
+        connected?;
+        let res = receiver?.activate(None).await;
+
+        assert!(matches!(
+            res,
+            Err(PylonError::Timeout(TimeoutStage::Receive))
+        ));
+
+        // The server accepts connections, but never answers the WebSocket upgrade.
+        let silent = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let config = PylonConfig {
+            rendezvous_url: format!("ws://{}/v1", silent.local_addr()?),
+            ..config
+        };
+        let res = Pylon::new(Mode::Receiver, Some("1-foo-bar".into()), &config).await;
+
+        assert!(matches!(
+            res,
+            Err(PylonError::Timeout(TimeoutStage::Connect))
+        ));
+        assert_eq!(res.err().map(|e| e.error_code()), Some("connect_timeout"));
+
+        // Only the rendezvous server (the service's upstream) timing out is a gateway timeout.
+        let statuses = [
+            TimeoutStage::Connect,
+            TimeoutStage::Handshake,
+            TimeoutStage::Receive,
+        ]
+        .map(|stage| routes::error_status(&PylonError::Timeout(stage)));
+
+        assert_eq!(
+            statuses,
+            [
+                Status::GatewayTimeout,
+                Status::RequestTimeout,
+                Status::RequestTimeout
+            ]
+        );
+
+        Ok(())
+    }
+
     /// Tests if a Payload can be created from a (&str, &str).
     #[test]
     fn test_payload_from() {
@@ -87,6 +296,12 @@ mod tests {
             code: code.into(),
             time: None,
             checksum: Some(digest(msg)),
+            file_name: None,
+            size: None,
+            seq: None,
+            encryption: None,
+            verified: false,
+            schema_version: None,
         };
         let derived_payload = Payload::from((msg, code));
 
@@ -109,6 +324,12 @@ mod tests {
             code: code.into(),
             time: None,
             checksum: Some(digest(msg)),
+            file_name: None,
+            size: None,
+            seq: None,
+            encryption: None,
+            verified: false,
+            schema_version: None,
         };
         let derived_payload: Payload = (msg, code).into();
 
@@ -117,81 +338,1812 @@ mod tests {
         assert_eq!(payload.length, derived_payload.length);
     }
 
-    /// Tests the high-level API endpoints' responses.
-    #[tokio::test]
-    async fn test_api_endpoints() -> Result<(), ThreadSafeError> {
-        use pylon_web::{routes, Response};
+    /// Tests that payloads are only verified when their message matches the length and checksum.
+    #[test]
+    fn test_payload_verify() {
+        let mut payload = Payload::from(("Hello world", "1-hello-world"));
+
+        assert!(payload.verify().is_ok());
+        assert!(payload.verified);
+
+        let tampered = [
+            Payload {
+                message: Some("Hello world!".into()),
+                verified: false,
+                ..payload.clone()
+            },
+            Payload {
+                length: Some(12),
+                verified: false,
+                ..payload.clone()
+            },
+            Payload {
+                checksum: None,
+                verified: false,
+                ..payload.clone()
+            },
+        ];
+
+        for mut payload in tampered {
+            assert!(matches!(payload.verify(), Err(PylonError::Integrity(_))));
+            assert!(!payload.verified);
+        }
+    }
+
+    /// Tests that payloads in unknown schema versions are rejected, and that unversioned payloads
+    /// from older peers are still accepted.
+    #[test]
+    fn test_payload_schema_version() {
+        use pylon_web::routes::error_status;
 
         use rocket::http::Status;
+
+        let payload = Payload::from(("Hello world", "1-hello-world"));
+
+        for version in [None, Some(PAYLOAD_SCHEMA_VERSION)] {
+            let payload = Payload {
+                schema_version: version,
+                ..payload.clone()
+            };
+
+            assert!(payload.check_schema_version().is_ok());
+        }
+
+        let newer = Payload {
+            schema_version: Some(PAYLOAD_SCHEMA_VERSION + 1),
+            ..payload.clone()
+        };
+        let err = newer.check_schema_version().err();
+
+        assert!(matches!(
+            err,
+            Some(PylonError::UnsupportedSchemaVersion(v)) if v == PAYLOAD_SCHEMA_VERSION + 1
+        ));
+
+        if let Some(err) = err {
+            assert_eq!(err.error_code(), "unsupported_schema_version");
+            assert_eq!(error_status(&err), Status::UnprocessableEntity);
+        }
+
+        // Payloads from peers that predate versioning have no version field.
+        let unversioned: Payload =
+            serde_json::from_str(r#"{"message": "Hello", "code": "1-hello"}"#).unwrap_or_default();
+
+        assert_eq!(unversioned.schema_version, None);
+        assert_eq!(unversioned.message.as_deref(), Some("Hello"));
+    }
+
+    /// Tests that encrypted payloads can only be decrypted with the right passphrase, and go through
+    /// the wormhole as opaque ciphertext.
+    #[tokio::test]
+    async fn test_e2e_encryption() -> Result<(), ThreadSafeError> {
+        use pylon_web::crypto::{self, KdfParams};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+        let mut sender = Pylon::new(Mode::Sender, None, &config).await?;
+        let code = sender.code.clone().ok_or("Code generation failed")?;
+
+        // Cheap key derivation parameters keep the (unoptimized) test fast.
+        let kdf = KdfParams::new(1024, 1, 1);
+        let plaintext = Payload::from(("Hello world", code.as_str()));
+        let encrypted = crypto::encrypt_with(&plaintext, "correct horse", kdf)?;
+
+        assert_ne!(encrypted.message, plaintext.message);
+        assert!(encrypted.encryption.is_some());
+        assert!(crypto::validate(&encrypted).is_ok());
+
+        let (sent, received) = tokio::join!(
+            async {
+                sender.connect().await?;
+                sender.activate(Some(&encrypted)).await
+            },
+            async {
+                Pylon::new(Mode::Receiver, Some(code.clone()), &config)
+                    .await?
+                    .activate(None)
+                    .await
+            }
+        );
+        sent?;
+        let received = received?.ok_or("Empty payload received")?;
+
+        assert!(received.verified);
+        assert_eq!(received.message, encrypted.message);
+
+        let mut decrypted = crypto::decrypt(&received, "correct horse")?;
+
+        assert_eq!(decrypted.message, plaintext.message);
+        assert_eq!(decrypted.encryption, None);
+        assert!(decrypted.verify().is_ok());
+        assert!(matches!(
+            crypto::decrypt(&received, "wrong horse"),
+            Err(PylonError::Crypto(_))
+        ));
+
+        // The service rejects encryption parameters that would be too costly for the receiver.
+        let mut costly = received.clone();
+        if let Some(encryption) = costly.encryption.as_mut() {
+            encryption.kdf.memory_kib = u32::MAX;
+        }
+
+        assert!(matches!(
+            crypto::validate(&costly),
+            Err(PylonError::InvalidPayload(_))
+        ));
+
+        Ok(())
+    }
+
+    /// Tests that the `pylon` CLI interoperates with the library, with JSON output and exit codes.
+    #[tokio::test]
+    async fn test_cli() -> Result<(), ThreadSafeError> {
+        use std::io::{BufRead, BufReader};
+        use std::process::{Command, Output, Stdio};
+
+        use pylon_web::crypto::{self, KdfParams};
+
+        use serde_json::Value;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+        let pylon = |args: &[&str]| {
+            let mut command = Command::new(env!("CARGO_BIN_EXE_pylon"));
+            command
+                .args(["--json", "--rendezvous-url", &rendezvous.url()])
+                .args(args)
+                .stdin(Stdio::null());
+            command
+        };
+        // The rendezvous server runs on the test runtime, so processes are waited for elsewhere.
+        let run = |mut command: Command| {
+            tokio::task::spawn_blocking(move || command.output().map_err(ThreadSafeError::from))
+        };
+        let events = |output: &Output| -> Vec<Value> {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        };
+
+        // The sender prints the code as soon as it's generated, and the receiver prints the message.
+        let mut sender = pylon(&["send", "Hello world"])
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = sender.stdout.take().ok_or("No sender stdout")?;
+        let (line, stdout) = tokio::task::spawn_blocking(move || {
+            let mut stdout = BufReader::new(stdout);
+            let mut line = String::new();
+            stdout.read_line(&mut line).map(|_| (line, stdout))
+        })
+        .await??;
+        let event: Value = serde_json::from_str(&line)?;
+        let code = event["code"].as_str().ok_or("No code printed")?.to_string();
+
+        assert_eq!(event["event"], "code");
+
+        let output = run(pylon(&["receive", &code])).await??;
+        let received = events(&output);
+
+        assert!(output.status.success());
+        assert_eq!(received[0]["event"], "received");
+        assert_eq!(received[0]["payload"]["message"], "Hello world");
+        assert_eq!(received[0]["payload"]["verified"], true);
+
+        let (rest, status) = tokio::task::spawn_blocking(move || {
+            let rest: Vec<String> = stdout.lines().map_while(Result::ok).collect();
+            sender.wait().map(|status| (rest, status))
+        })
+        .await??;
+
+        assert!(status.success());
+        assert!(rest[0].contains("\"sent\""));
+
+        // Encrypted messages sent by other clients can't be read without the right passphrase.
+        let mut lib_sender = Pylon::new(Mode::Sender, None, &config).await?;
+        let code = lib_sender.code.clone().ok_or("Code generation failed")?;
+        let payload = crypto::encrypt_with(
+            &Payload::from(("Hello world", code.as_str())),
+            "correct horse",
+            KdfParams::new(1024, 1, 1),
+        )?;
+
+        let (sent, output) = tokio::join!(
+            async {
+                lib_sender.connect().await?;
+                lib_sender.activate(Some(&payload)).await
+            },
+            run(pylon(&["receive", &code, "--passphrase", "wrong horse"]))
+        );
+        sent?;
+        let output = output??;
+
+        assert_eq!(output.status.code(), Some(7));
+        assert_eq!(events(&output)[0]["error"], "crypto_error");
+
+        // Malformed codes and bad configurations are rejected up front.
+        let output = run(pylon(&["receive", "not-a-code"])).await??;
+
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(events(&output)[0]["error"], "bad_code");
+
+        let output = run(pylon(&["send", "Hello", "--app-id", ""])).await??;
+
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(events(&output)[0]["error"], "invalid_config");
+
+        Ok(())
+    }
+
+    /// Tests the API client against a local Rocket instance, including its retries.
+    #[tokio::test]
+    async fn test_client() -> Result<(), ThreadSafeError> {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::time::Duration;
+
+        use pylon_web::client::{ClientError, PylonClient, Transport};
+
+        use rocket::http::{Method, Status};
         use rocket::local::asynchronous::Client;
-        use rocket::log::LogLevel;
-        use rocket::Config;
-        use rocket::{routes, uri};
 
-        // Turn off Rocket's debug logging, since it would pollute the test's output.
-        let conf = Config {
-            log_level: LogLevel::Off,
-            ..Config::debug_default()
+        use serde_json::json;
+
+        /// Fails the first requests with a server error, then dispatches to a local instance.
+        struct Flaky {
+            inner: Client,
+            status: Status,
+            body: String,
+            failures: u32,
+            attempts: AtomicU32,
+        }
+
+        #[rocket::async_trait]
+        impl Transport for Flaky {
+            async fn request(
+                &self,
+                method: Method,
+                path: &str,
+                body: Option<String>,
+            ) -> Result<(Status, String), ClientError> {
+                if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                    return Ok((self.status, self.body.clone()));
+                }
+
+                self.inner.request(method, path, body).await
+            }
+        }
+
+        let rendezvous = LocalRendezvous::start().await?;
+
+        let client = PylonClient::with_transport(local_client(rendezvous.config()).await?);
+        let info = client.code(Some(3)).await?;
+
+        assert_eq!(info.words, 3);
+
+        let payload = Payload::from(("Hello world", info.code.as_str()));
+        let (sent, received) = tokio::join!(client.send(&payload), client.receive(&info.code));
+        let (sent, received) = (sent?, received?);
+
+        assert_eq!(sent.message, payload.message);
+        assert!(received.verified);
+        assert_eq!(received.message, payload.message);
+
+        // Error responses are decoded into their status and machine-readable error code.
+        match client.send(&payload).await {
+            Err(ClientError::Api(e)) => {
+                assert_eq!(e.status, 404);
+                assert_eq!(e.error_code.as_deref(), Some("unknown_code"));
+            }
+            other => return Err(format!("Expected an API error, got {:?}", other.err()).into()),
+        }
+
+        let err = client.receive("not-a-code").await.err();
+
+        assert_eq!(
+            err.as_ref().and_then(ClientError::error_code),
+            Some("bad_code")
+        );
+
+        // Transient errors are retried, up to the configured number of retries.
+        let flaky_with = |status, body: &str, failures| {
+            let body = body.to_string();
+            let config = rendezvous.config();
+
+            async move {
+                Ok::<_, rocket::Error>(Flaky {
+                    inner: local_client(config).await?,
+                    status,
+                    body,
+                    failures,
+                    attempts: AtomicU32::new(0),
+                })
+            }
+        };
+        let flaky = |status, failures| flaky_with(status, "<html>Bad gateway</html>", failures);
+
+        let client = PylonClient::with_transport(flaky(Status::BadGateway, 2).await?)
+            .retries(2, Duration::from_millis(1));
+
+        assert!(client.code(None).await.is_ok());
+
+        let client = PylonClient::with_transport(flaky(Status::ServiceUnavailable, 2).await?)
+            .retries(1, Duration::from_millis(1));
+
+        match client.code(None).await {
+            Err(ClientError::Api(e)) => {
+                assert_eq!(e.status, 503);
+                assert_eq!(e.error_code, None);
+            }
+            other => return Err(format!("Expected an API error, got {:?}", other.err()).into()),
+        }
+
+        // Sends are not retried on gateway errors, since the code may already be used up.
+        let client = PylonClient::with_transport(flaky(Status::BadGateway, 1).await?)
+            .retries(2, Duration::from_millis(1));
+        let err = client.send(&payload).await.err();
+
+        assert!(matches!(err, Some(ClientError::Api(ref e)) if e.status == 502));
+
+        // Receives are only retried on timeouts if the receiver never completed its handshake.
+        let timeout = |status: Status, error_code: &str| {
+            json!({"code": status.code, "message": null, "error_code": error_code, "data": null})
+                .to_string()
         };
-        let client = Client::tracked(
-            rocket::build()
-                .configure(conf)
-                .mount("/", routes![routes::code, routes::send, routes::receive]),
+
+        for (status, error_code) in [
+            (Status::GatewayTimeout, "connect_timeout"),
+            (Status::RequestTimeout, "handshake_timeout"),
+        ] {
+            let client = PylonClient::with_transport(
+                flaky_with(status, &timeout(status, error_code), 1).await?,
+            )
+            .retries(2, Duration::from_millis(1));
+            let err = client.receive("not-a-code").await.err();
+
+            assert_eq!(
+                err.as_ref().and_then(ClientError::error_code),
+                Some("bad_code")
+            );
+        }
+
+        let status = Status::RequestTimeout;
+        let client = PylonClient::with_transport(
+            flaky_with(status, &timeout(status, "receive_timeout"), 1).await?,
         )
-        .await
-        .expect("invalid rocket instance");
+        .retries(2, Duration::from_millis(1));
+        let err = client.receive("not-a-code").await.err();
 
-        // Test `/code` endpoint and store its status and body (to retrieve the generated code).
-        let resp = client.get(uri!(routes::code)).dispatch().await;
-        let status = resp.status();
-        let body: Option<Response<String>> = resp.into_json().await;
+        assert_eq!(
+            err.as_ref().and_then(ClientError::error_code),
+            Some("receive_timeout")
+        );
 
-        assert_eq!(status, Status::Ok);
+        Ok(())
+    }
 
-        if let Some(body) = body {
-            if let Some(code) = body.data {
-                // Wrap client in an Arc, to make it shareable between threads.
-                let arc_client = Arc::new(client);
-                let code_copy = code.clone();
+    /// Tests that the OpenAPI specification describes exactly the API routes, and that it's served
+    /// along with the documentation UI.
+    #[tokio::test]
+    async fn test_openapi() -> Result<(), ThreadSafeError> {
+        use std::collections::BTreeSet;
 
-                // `/send` endpoint thread.
-                let client = Arc::clone(&arc_client);
-                let send_handle = tokio::spawn(async move {
-                    let payload: Payload = ("Hello world", code_copy.as_str()).into();
-                    let resp = client
-                        .post(uri!(routes::send))
-                        .json(&payload)
-                        .dispatch()
-                        .await;
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::openapi::ApiDoc;
+        use pylon_web::routes;
 
-                    assert_eq!(resp.status(), Status::Ok);
+        use rocket::http::Status;
 
-                    // Test response when payload not sent.
-                    let resp = client.post(uri!(routes::send)).dispatch().await;
+        use serde_json::Value;
 
-                    assert_ne!(resp.status(), Status::Ok);
-                });
+        use utoipa::OpenApi;
 
-                // `/receive` endpoint thread.
-                let client = Arc::clone(&arc_client);
-                let recv_handle = tokio::spawn(async move {
-                    let payload: Payload = ("", code.as_str()).into();
-                    let resp = client
-                        .post(uri!(routes::receive))
-                        .json(&payload)
-                        .dispatch()
-                        .await;
+        let spec = serde_json::to_value(ApiDoc::openapi())?;
 
-                    assert_eq!(resp.status(), Status::Ok);
+        // Every route must be documented, and every documented operation must be routed.
+        let routed: BTreeSet<(String, String)> = routes::api()
+            .iter()
+            .map(|route| (API_PREFIX, route))
+            .chain(routes::monitoring().iter().map(|route| ("", route)))
+            .map(|(prefix, route)| {
+                let path = format!("{}{}", prefix, route.uri.path())
+                    .replace('<', "{")
+                    .replace('>', "}");
 
-                    // Test response when payload not sent.
-                    let resp = client.post(uri!(routes::send)).dispatch().await;
+                (route.method.as_str().to_lowercase(), path)
+            })
+            .collect();
+        let documented: BTreeSet<(String, String)> = spec["paths"]
+            .as_object()
+            .ok_or("Specification has no paths")?
+            .iter()
+            .flat_map(|(path, item)| {
+                item.as_object()
+                    .into_iter()
+                    .flat_map(|item| item.keys())
+                    .filter(|method| {
+                        ["get", "put", "post", "delete", "patch", "head", "options"]
+                            .contains(&method.as_str())
+                    })
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect();
 
-                    assert_ne!(resp.status(), Status::Ok);
-                });
+        assert_eq!(routed, documented);
 
-                send_handle.await?;
-                recv_handle.await?;
+        // Error responses must match the documented error envelope.
+        let body = serde_json::to_value(routes::error_body(&PylonError::UnknownCode))?;
+        let required = spec["components"]["schemas"]["ErrorResponse"]["required"]
+            .as_array()
+            .ok_or("ErrorResponse has no required fields")?;
+
+        for field in required.iter().filter_map(Value::as_str) {
+            assert!(!body[field].is_null(), "missing error field '{}'", field);
+        }
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(rendezvous.config()).await?;
+
+        let resp = client.get("/openapi.json").dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+        assert_eq!(resp.into_json::<Value>().await, Some(spec));
+
+        let resp = client.get("/docs").dispatch().await;
+
+        assert_eq!(resp.status(), Status::Found);
+
+        let resp = client.get("/docs/").dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+        assert!(resp
+            .into_string()
+            .await
+            .unwrap_or_default()
+            .contains("swagger-ui"));
+
+        Ok(())
+    }
+
+    /// Tests that the frontend's static files are served only when a static directory is
+    /// configured.
+    #[tokio::test]
+    async fn test_build_static() -> Result<(), ThreadSafeError> {
+        use std::fs;
+
+        use rocket::http::Status;
+
+        let dir = std::env::temp_dir().join(format!("pylon-static-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("index.html"), "<h1>pylon</h1>")?;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(rendezvous.config()).await?;
+        let resp = client.get("/").dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+        assert_eq!(resp.into_string().await.as_deref(), Some("Hello, world!"));
+
+        let client = local_client(PylonConfig {
+            static_dir: Some(dir.clone()),
+            ..rendezvous.config()
+        })
+        .await?;
+        let resp = client.get("/").dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+        assert!(resp.headers().contains("Content-Security-Policy"));
+        assert_eq!(resp.into_string().await.as_deref(), Some("<h1>pylon</h1>"));
+
+        let resp = client.get("/healthz").dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+
+        fs::remove_dir_all(&dir)?;
+
+        Ok(())
+    }
+
+    /// Tests that the default configuration is valid and that bad rendezvous URLs and code lengths
+    /// are rejected.
+    #[test]
+    fn test_config_validate() {
+        assert!(PylonConfig::default().validate().is_ok());
+
+        for url in ["not a url", "http://localhost:4000/v1", "ws:///v1"] {
+            let config = PylonConfig {
+                rendezvous_url: url.into(),
+                ..Default::default()
+            };
+
+            assert!(config.validate().is_err(), "{} should be rejected", url);
+        }
+
+        for (code_length, min_code_length) in [(1, 2), (9, 2), (1, 0)] {
+            let config = PylonConfig {
+                code_length,
+                min_code_length,
+                ..Default::default()
+            };
+
+            assert!(config.validate().is_err());
+        }
+
+        let config = PylonConfig {
+            timeouts: pylon_web::config::TimeoutConfig {
+                handshake: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(config.validate().is_err());
+    }
+
+    /// Tests that configuration files are overridden by command-line flags, and that invalid values
+    /// are reported as errors.
+    #[test]
+    fn test_config_layering() {
+        use clap::{Args, Command, FromArgMatches};
+        use pylon_web::config::{self, ConfigArgs, RateBudget, TimeoutArgs};
+        use rocket::data::ByteUnit;
+        use std::fs;
+        use std::net::{IpAddr, Ipv6Addr};
+
+        let dir = std::env::temp_dir().join(format!("pylon-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("Pylon.toml");
+
+        // Without a config file, the service listens on the default port.
+        let args = ConfigArgs {
+            config: Some(file.clone()),
+            ..Default::default()
+        };
+        let (_, server) = config::load(&config::figment(&args)).unwrap();
+
+        assert_eq!(server.port, 8080);
+
+        fs::write(
+            &file,
+            "[default]\naddress = \"::1\"\nport = 9000\ncode_length = 4\ncode_ttl = 60\n\n\
+             [default.timeouts]\nconnect = 3\nhandshake = 20\n",
+        )
+        .unwrap();
+
+        let args = ConfigArgs {
+            config: Some(file.clone()),
+            port: Some(9001),
+            code_ttl: Some(120),
+            reap_interval: Some(5),
+            timeouts: TimeoutArgs {
+                handshake_timeout: Some(40),
+                ..Default::default()
+            },
+            upload_limit: Some(ByteUnit::Mebibyte(64)),
+            static_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        let (config, server) = config::load(&config::figment(&args)).unwrap();
+
+        assert_eq!(server.address, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(server.port, 9001);
+        assert_eq!(server.limits.get("file"), Some(ByteUnit::Mebibyte(64)));
+        assert_eq!(config.code_length, 4);
+        assert_eq!(config.code_ttl, 120);
+        assert_eq!(config.reap_interval, 5);
+        assert_eq!(config.timeouts.connect, 3);
+        assert_eq!(config.timeouts.handshake, 40);
+        assert_eq!(config.timeouts.receive, 30);
+        assert_eq!(config.static_dir, Some(dir.clone()));
+
+        // Rate limits are set through flags as well.
+        let matches = ConfigArgs::augment_args(Command::new("pylon-web"))
+            .try_get_matches_from([
+                "pylon-web",
+                "--rate-limit",
+                "false",
+                "--receive-rate-limit",
+                "3,6",
+            ])
+            .unwrap();
+        let flags = ConfigArgs::from_arg_matches(&matches).unwrap();
+        let (config, _) = config::load(&config::figment(&ConfigArgs {
+            config: Some(file.clone()),
+            ..flags
+        }))
+        .unwrap();
+
+        assert!(!config.rate_limit.enabled);
+        assert_eq!(
+            config.rate_limit.receive,
+            RateBudget {
+                burst: 3,
+                per_minute: 6
+            }
+        );
+        assert_eq!(config.rate_limit.code.burst, 10);
+        assert!(ConfigArgs::augment_args(Command::new("pylon-web"))
+            .try_get_matches_from(["pylon-web", "--code-rate-limit", "10"])
+            .is_err());
+
+        let args = ConfigArgs {
+            static_dir: Some(dir.join("missing")),
+            ..args
+        };
+
+        assert!(config::load(&config::figment(&args)).is_err());
+
+        fs::write(&file, "[default]\nport = \"http\"\n").unwrap();
+        let args = ConfigArgs {
+            config: Some(file),
+            ..Default::default()
+        };
+
+        assert!(config::load(&config::figment(&args)).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Tests that the in-memory store hands out each stored sender exactly once.
+    #[tokio::test]
+    async fn test_memory_store() -> Result<(), ThreadSafeError> {
+        use std::time::{Duration, SystemTime};
+
+        use pylon_web::store::{MemoryStore, SessionStore};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+        let store = MemoryStore::default();
+        let ttl = Duration::from_secs(60);
+        let mut codes = Vec::new();
+
+        for _ in 0..2 {
+            let mut pylon = Pylon::new(Mode::Sender, None, &config).await?;
+            let code = pylon.code.take().ok_or("Code generation failed")?;
+            let expires_at = store.insert(code.clone(), pylon, ttl).await;
+
+            assert!(expires_at > SystemTime::now());
+            codes.push(code);
+        }
+
+        let mut listed = store.list().await;
+        listed.sort();
+        codes.sort();
+
+        assert_eq!(listed, codes);
+        assert!(store.take("1-unknown-code").await.is_none());
+        assert!(store.take(&codes[0]).await.is_some());
+        assert!(store.take(&codes[0]).await.is_none());
+        assert_eq!(store.list().await, [codes[1].clone()]);
+        assert!(store.ping().await.is_ok());
+
+        Ok(())
+    }
+
+    /// Tests that MemoryStore sessions expire after their TTL, and that expired sessions are
+    /// closed when evicted.
+    #[tokio::test]
+    async fn test_memory_store_expiry() -> Result<(), ThreadSafeError> {
+        use std::time::Duration;
+
+        use pylon_web::store::{MemoryStore, SessionStore};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+        let store = MemoryStore::default();
+        let ttl = Duration::from_millis(200);
+        let mut codes = Vec::new();
+
+        for _ in 0..3 {
+            let mut pylon = Pylon::new(Mode::Sender, None, &config).await?;
+            codes.push(pylon.code.take().ok_or("Code generation failed")?);
+            store
+                .insert(codes[codes.len() - 1].clone(), pylon, ttl)
+                .await;
+        }
+
+        assert!(store.expire().await.is_empty());
+
+        tokio::time::sleep(ttl).await;
+
+        // Expired sessions are kept until they're evicted, but can't be taken anymore.
+        assert_eq!(store.list().await.len(), 3);
+        assert!(store.take(&codes[0]).await.is_none());
+        assert_eq!(rendezvous.nameplates().await.len(), 3);
+
+        let mut expired = store.expire().await;
+        expired.sort();
+        codes.sort();
+
+        assert_eq!(expired, codes);
+        assert!(store.list().await.is_empty());
+        assert!(rendezvous.nameplates().await.is_empty());
+        assert_eq!(rendezvous.open_mailboxes().await, 0);
+
+        Ok(())
+    }
+
+    /// Tests that the reaper evicts and closes expired codes, and marks their transfers as expired.
+    #[tokio::test]
+    async fn test_reaper() -> Result<(), ThreadSafeError> {
+        use std::time::Duration;
+
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(PylonConfig {
+            code_ttl: 5,
+            reap_interval: 1,
+            ..rendezvous.config()
+        })
+        .await?;
+
+        let resp = client.get(format!("{}/code", API_PREFIX)).dispatch().await;
+        let info: Response<CodeInfo> = resp.into_json().await.ok_or("invalid code response")?;
+        let code = info.data.ok_or("no code generated")?.code;
+
+        assert_eq!(rendezvous.nameplates().await.len(), 1);
+        assert_eq!(rendezvous.open_mailboxes().await, 1);
+
+        // The code is evicted on the first tick after it expires, so poll for it rather than guess
+        // when that tick happens.
+        let mut state = TransferState::Pending;
+
+        for _ in 0..300 {
+            let resp = client
+                .get(format!("{}/status/{}", API_PREFIX, code))
+                .dispatch()
+                .await;
+            let status: Option<Response<TransferStatus>> = resp.into_json().await;
+
+            if let Some(TransferStatus { state: current, .. }) = status.and_then(|s| s.data) {
+                state = current;
+            }
+
+            if state != TransferState::Pending {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        assert_eq!(state, TransferState::Expired);
+        assert!(rendezvous.nameplates().await.is_empty());
+        assert_eq!(rendezvous.open_mailboxes().await, 0);
+
+        Ok(())
+    }
+
+    /// Tests that a queued payload expires along with its code, rather than a full TTL after it was
+    /// sent.
+    #[tokio::test]
+    async fn test_send_expiry() -> Result<(), ThreadSafeError> {
+        use std::time::{Duration, SystemTime};
+
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(PylonConfig {
+            code_ttl: 6,
+            ..rendezvous.config()
+        })
+        .await?;
+
+        let resp = client.get(format!("{}/code", API_PREFIX)).dispatch().await;
+        let info: Response<CodeInfo> = resp.into_json().await.ok_or("invalid code response")?;
+        let CodeInfo {
+            code, expires_at, ..
+        } = info.data.ok_or("no code generated")?;
+
+        // Send halfway through the TTL, so that the code's expiry and a full TTL after the send are
+        // seconds apart.
+        tokio::time::sleep(Duration::from_secs(3)).await;
+
+        let resp = client
+            .post(format!("{}/send", API_PREFIX))
+            .json(&Payload::from(("Hello world", code.as_str())))
+            .dispatch()
+            .await;
+
+        assert_eq!(resp.status().code, 202);
+
+        let check_at = expires_at + Duration::from_millis(1500);
+        tokio::time::sleep(
+            check_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+        )
+        .await;
+
+        let resp = client
+            .get(format!("{}/status/{}", API_PREFIX, code))
+            .dispatch()
+            .await;
+        let status: Response<TransferStatus> =
+            resp.into_json().await.ok_or("invalid status response")?;
+
+        assert_eq!(
+            status.data.map(|status| status.state),
+            Some(TransferState::Expired)
+        );
+
+        Ok(())
+    }
+
+    /// Tests that a queued payload fails once the handshake timeout elapses, even though its code is
+    /// still valid.
+    #[tokio::test]
+    async fn test_send_handshake_timeout() -> Result<(), ThreadSafeError> {
+        use std::time::Duration;
+
+        use pylon_web::config::TimeoutConfig;
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::core::TimeoutStage;
+        use pylon_web::Response;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(PylonConfig {
+            timeouts: TimeoutConfig {
+                handshake: 1,
+                ..Default::default()
+            },
+            ..rendezvous.config()
+        })
+        .await?;
+
+        let resp = client.get(format!("{}/code", API_PREFIX)).dispatch().await;
+        let info: Response<CodeInfo> = resp.into_json().await.ok_or("invalid code response")?;
+        let code = info.data.ok_or("no code generated")?.code;
+
+        let resp = client
+            .post(format!("{}/send", API_PREFIX))
+            .json(&Payload::from(("Hello world", code.as_str())))
+            .dispatch()
+            .await;
+
+        assert_eq!(resp.status().code, 202);
+
+        let mut status = None;
+
+        for _ in 0..300 {
+            let resp = client
+                .get(format!("{}/status/{}", API_PREFIX, code))
+                .dispatch()
+                .await;
+            let body: Option<Response<TransferStatus>> = resp.into_json().await;
+            status = body.and_then(|body| body.data);
+
+            if status.as_ref().map(|status| status.state) != Some(TransferState::Pending) {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        let status = status.ok_or("missing transfer status")?;
+
+        assert_eq!(status.state, TransferState::Failed);
+        assert_eq!(
+            status.error,
+            Some(PylonError::Timeout(TimeoutStage::Handshake).to_string())
+        );
+
+        Ok(())
+    }
+
+    /// Tests that a receive waiting for its payload is aborted when the server shuts down, and that
+    /// its wormhole is closed cleanly.
+    #[tokio::test]
+    async fn test_receive_abort() -> Result<(), ThreadSafeError> {
+        use std::time::Duration;
+
+        use magic_wormhole::Wormhole;
+
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::{routes, Response};
+
+        use rocket::uri;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+        let client = local_client(config.clone()).await?;
+
+        // A sender that completes the handshake, but never sends its payload.
+        let (welcome, connect) = Wormhole::connect_without_code(config.app_config(), 2).await?;
+        let code = welcome.code.0;
+        let sender = async {
+            let wormhole = connect.await?;
+
+            // Only abort once the receiver completed its handshake as well.
+            for _ in 0..100 {
+                let resp = client.get(uri!(routes::metrics)).dispatch().await;
+                let body = resp.into_string().await.unwrap_or_default();
+
+                if body.contains("pylon_handshake_duration_seconds_count 1") {
+                    break;
+                }
+
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+
+            client.rocket().shutdown().notify();
+
+            Ok::<_, ThreadSafeError>(wormhole)
+        };
+        let receiver = client
+            .post(format!("{}/receive", API_PREFIX))
+            .json(&Payload::from(("", code.as_str())))
+            .dispatch();
+
+        let (sender, resp) = tokio::join!(sender, receiver);
+        let sender = sender?;
+
+        assert_eq!(resp.status().code, 503);
+
+        let body: Response<Payload> = resp.into_json().await.ok_or("invalid error response")?;
+
+        assert_eq!(body.error_code.as_deref(), Some("aborted"));
+
+        // The receiver's wormhole is closed in the background.
+        for _ in 0..100 {
+            if !rendezvous.moods().await.is_empty() {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        assert_eq!(rendezvous.moods().await, ["happy"]);
+
+        sender.close().await?;
+
+        Ok(())
+    }
+
+    /// Tests that `/receive` rejects a payload sent in a newer schema version, end to end.
+    #[tokio::test]
+    async fn test_schema_version_round_trip() -> Result<(), ThreadSafeError> {
+        use magic_wormhole::Wormhole;
+
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let config = rendezvous.config();
+        let client = local_client(config.clone()).await?;
+
+        // A newer peer, which stamps its payloads with a schema version this service can't read.
+        let (welcome, connect) = Wormhole::connect_without_code(config.app_config(), 2).await?;
+        let code = welcome.code.0;
+        let payload = Payload {
+            schema_version: Some(PAYLOAD_SCHEMA_VERSION + 1),
+            ..Payload::from(("Hello world", code.as_str()))
+        };
+        let sender = async {
+            let mut wormhole = connect.await?;
+            wormhole.send_json(&payload).await?;
+
+            Ok::<_, ThreadSafeError>(wormhole)
+        };
+        let receiver = client
+            .post(format!("{}/receive", API_PREFIX))
+            .json(&Payload::from(("", code.as_str())))
+            .dispatch();
+
+        let (sender, resp) = tokio::join!(sender, receiver);
+        sender?.close().await?;
+
+        assert_eq!(resp.status().code, 422);
+
+        let body: Response<Payload> = resp.into_json().await.ok_or("invalid error response")?;
+
+        assert_eq!(
+            body.error_code.as_deref(),
+            Some("unsupported_schema_version")
+        );
+
+        Ok(())
+    }
+
+    /// Tests that a ChecksumReader accepts matching data and rejects truncated or corrupted data.
+    #[tokio::test]
+    async fn test_checksum_reader() {
+        use futures::io::{AsyncReadExt, Cursor};
+
+        let data = b"Hello world";
+        let checksum = digest("Hello world");
+        let size = data.len() as u64;
+
+        let mut buf = Vec::new();
+        let mut reader = ChecksumReader::new(Cursor::new(data), size, checksum.clone());
+        reader
+            .read_to_end(&mut buf)
+            .await
+            .expect("valid data rejected");
+
+        assert_eq!(buf, data);
+
+        let mut reader = ChecksumReader::new(Cursor::new(&data[..5]), size, checksum.clone());
+
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+
+        let mut reader = ChecksumReader::new(Cursor::new(b"Hello World"), size, checksum);
+
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+    }
+
+    /// Tests that a file uploaded to a sender is streamed to the receiver with its metadata.
+    #[tokio::test]
+    async fn test_file_transfer() -> Result<(), ThreadSafeError> {
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        use rocket::http::{ContentType, Status};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = Arc::new(local_client(rendezvous.config()).await?);
+
+        let resp = client.get(format!("{}/code", API_PREFIX)).dispatch().await;
+        let info: Response<CodeInfo> = resp.into_json().await.ok_or("invalid code response")?;
+        let code = info.data.ok_or("no code generated")?.code;
+
+        let contents = "Hello world\n".repeat(1024);
+        let form = format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"code\"\r\n\r\n{}\r\n\
+             --boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"hello.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\n{}\r\n--boundary--\r\n",
+            code, contents
+        );
+
+        // When both peers share a host, the sender may miss the receiver's final transit ack, and
+        // magic-wormhole then waits for the peer indefinitely. So the upload is only driven in the
+        // background, and the transfer is checked on the receiving side.
+        let sender = Arc::clone(&client);
+        let upload = tokio::spawn(async move {
+            sender
+                .post(format!("{}/send/file", API_PREFIX))
+                .header(
+                    ContentType::new("multipart", "form-data")
+                        .with_params(("boundary", "boundary")),
+                )
+                .body(form)
+                .dispatch()
+                .await
+                .status()
+        });
+
+        let resp = client
+            .post(format!("{}/receive/file", API_PREFIX))
+            .json(&Payload {
+                code: code.clone(),
+                ..Default::default()
+            })
+            .dispatch()
+            .await;
+
+        assert_eq!(resp.status(), Status::Ok);
+
+        let headers: Vec<Option<&str>> =
+            ["Content-Disposition", "X-File-Size", "X-Checksum-SHA256"]
+                .into_iter()
+                .map(|name| resp.headers().get_one(name))
+                .collect();
+
+        assert_eq!(
+            headers,
+            [
+                Some("attachment; filename=\"hello.txt\""),
+                Some(contents.len().to_string().as_str()),
+                Some(digest(contents.as_str()).as_str()),
+            ]
+        );
+        assert_eq!(resp.into_string().await.as_deref(), Some(contents.as_str()));
+
+        upload.abort();
+
+        Ok(())
+    }
+
+    /// Tests that a file upload gives up once its code expires, instead of waiting for a receiver
+    /// forever.
+    #[tokio::test]
+    async fn test_send_file_expiry() -> Result<(), ThreadSafeError> {
+        use std::time::Duration;
+
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        use rocket::http::{ContentType, Status};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(PylonConfig {
+            code_ttl: 2,
+            ..rendezvous.config()
+        })
+        .await?;
+
+        let resp = client.get(format!("{}/code", API_PREFIX)).dispatch().await;
+        let info: Response<CodeInfo> = resp.into_json().await.ok_or("invalid code response")?;
+        let code = info.data.ok_or("no code generated")?.code;
+
+        let form = format!(
+            "--boundary\r\nContent-Disposition: form-data; name=\"code\"\r\n\r\n{}\r\n\
+             --boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"hello.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\nHello world\r\n--boundary--\r\n",
+            code
+        );
+        let upload = client
+            .post(format!("{}/send/file", API_PREFIX))
+            .header(
+                ContentType::new("multipart", "form-data").with_params(("boundary", "boundary")),
+            )
+            .body(form)
+            .dispatch();
+
+        // No receiver ever connects, so the upload only returns once the code expires.
+        let resp = tokio::time::timeout(Duration::from_secs(30), upload).await?;
+
+        assert_eq!(resp.status(), Status::RequestTimeout);
+
+        let body: Response<Payload> = resp.into_json().await.ok_or("invalid error response")?;
+
+        assert_eq!(body.error_code.as_deref(), Some("handshake_timeout"));
+
+        let resp = client
+            .get(format!("{}/status/{}", API_PREFIX, code))
+            .dispatch()
+            .await;
+        let status: Response<TransferStatus> =
+            resp.into_json().await.ok_or("invalid status response")?;
+
+        assert_eq!(
+            status.data.map(|status| status.state),
+            Some(TransferState::Expired)
+        );
+
+        Ok(())
+    }
+
+    /// Tests that API errors are reported with the matching HTTP status and error code.
+    #[tokio::test]
+    async fn test_api_errors() -> Result<(), ThreadSafeError> {
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        use rocket::http::{ContentType, Status};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(rendezvous.config()).await?;
+        let send = format!("{}/send", API_PREFIX);
+        let receive = format!("{}/receive", API_PREFIX);
+
+        let cases = [
+            (
+                send.as_str(),
+                Payload::from(("Hello world", "1-unknown-code")),
+                Status::NotFound,
+                "unknown_code",
+            ),
+            (
+                send.as_str(),
+                Payload {
+                    code: "1-hello-world".into(),
+                    ..Default::default()
+                },
+                Status::BadRequest,
+                "empty_payload",
+            ),
+            (
+                receive.as_str(),
+                Payload::from(("", "not-a-code")),
+                Status::Forbidden,
+                "bad_code",
+            ),
+        ];
+
+        for (uri, payload, status, error_code) in cases {
+            let resp = client.post(uri).json(&payload).dispatch().await;
+
+            assert_eq!(resp.status(), status);
+
+            let body: Response<Payload> = resp.into_json().await.expect("invalid error body");
+
+            assert_eq!(body.code, status.code);
+            assert_eq!(body.error_code.as_deref(), Some(error_code));
+        }
+
+        let resp = client
+            .post(send.as_str())
+            .header(ContentType::JSON)
+            .body("{")
+            .dispatch()
+            .await;
+        let body: Response<Payload> = resp.into_json().await.expect("invalid error body");
+
+        assert_eq!(body.error_code.as_deref(), Some("invalid_payload"));
+
+        let resp = client
+            .get(format!("{}/status/1-unknown-code", API_PREFIX))
+            .dispatch()
+            .await;
+
+        assert_eq!(resp.status(), Status::NotFound);
+
+        for words in ["1", "9", "two"] {
+            let resp = client
+                .get(format!("{}/code?words={}", API_PREFIX, words))
+                .dispatch()
+                .await;
+
+            assert_eq!(resp.status(), Status::BadRequest);
+
+            let body: Response<CodeInfo> = resp.into_json().await.expect("invalid error body");
+
+            assert_eq!(body.error_code.as_deref(), Some("invalid_code_length"));
+        }
+
+        Ok(())
+    }
+
+    /// Tests that transfer lifecycle events are streamed to subscribers.
+    #[tokio::test]
+    async fn test_event_stream() -> Result<(), ThreadSafeError> {
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::controllers::TransferTracker;
+        use pylon_web::core::TransferEvent;
+
+        use rocket::http::{ContentType, Status};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(rendezvous.config()).await?;
+        let tracker = client
+            .rocket()
+            .state::<TransferTracker>()
+            .ok_or("tracker not managed")?;
+
+        let code = "1-hello-world";
+        tracker.update(code, TransferState::Pending, None).await;
+        tracker.emit(code, TransferEvent::MailboxAllocated).await;
+
+        let resp = client
+            .get(format!("{}/events/{}", API_PREFIX, code))
+            .dispatch()
+            .await;
+
+        assert_eq!(resp.status(), Status::Ok);
+        assert_eq!(resp.content_type(), Some(ContentType::EventStream));
+
+        tracker.emit(code, TransferEvent::PeerConnected).await;
+        tracker.emit(code, TransferEvent::PayloadSent).await;
+        tracker.emit(code, TransferEvent::Expired).await;
+
+        let body = resp.into_string().await.expect("invalid event stream");
+        let events: Vec<&str> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("event:"))
+            .collect();
+
+        assert_eq!(
+            events,
+            [
+                "status",
+                "mailbox_allocated",
+                "peer_connected",
+                "payload_sent"
+            ]
+        );
+
+        Ok(())
+    }
+
+    /// Tests origin matching and validation of the CORS policy.
+    #[test]
+    fn test_cors_policy() {
+        use pylon_web::config::CorsConfig;
+
+        let cors = CorsConfig {
+            allowed_origins: vec![
+                "https://example.com".into(),
+                "https://*.internal.example.com".into(),
+            ],
+            ..Default::default()
+        };
+
+        assert!(cors.validate().is_ok());
+        assert!(cors.allows_origin("https://example.com"));
+        assert!(cors.allows_origin("https://app.internal.example.com"));
+        assert!(cors.allows_origin("https://a.b.internal.example.com"));
+        assert!(!cors.allows_origin("https://internal.example.com"));
+        assert!(!cors.allows_origin("https://evilinternal.example.com"));
+        assert!(!cors.allows_origin("http://app.internal.example.com"));
+        assert!(!cors.allows_origin("https://app.internal.example.com:8443"));
+        assert!(!cors.allows_origin("https://example.com.evil.com"));
+
+        assert!(cors.allows_headers("content-type"));
+        assert!(!cors.allows_headers("Content-Type, X-Custom"));
+
+        let credentialed_wildcard = CorsConfig {
+            allow_credentials: true,
+            ..Default::default()
+        };
+
+        assert!(credentialed_wildcard.validate().is_err());
+
+        let bad_origin = CorsConfig {
+            allowed_origins: vec!["https://example.com/path".into()],
+            ..Default::default()
+        };
+
+        assert!(bad_origin.validate().is_err());
+    }
+
+    /// Tests that CORS headers and preflight responses follow the configured policy.
+    #[tokio::test]
+    async fn test_cors_fairing() -> Result<(), ThreadSafeError> {
+        use pylon_web::config::CorsConfig;
+        use pylon_web::consts::API_PREFIX;
+
+        use rocket::http::{Header, Status};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(PylonConfig {
+            cors: CorsConfig {
+                allowed_origins: vec!["https://*.example.com".into()],
+                allow_credentials: true,
+                ..Default::default()
+            },
+            ..rendezvous.config()
+        })
+        .await?;
+        let status = format!("{}/status/1-hello-world", API_PREFIX);
+
+        let origin = "https://app.example.com";
+        let resp = client
+            .options(status.as_str())
+            .header(Header::new("Origin", origin))
+            .header(Header::new("Access-Control-Request-Method", "GET"))
+            .header(Header::new(
+                "Access-Control-Request-Headers",
+                "Content-Type",
+            ))
+            .dispatch()
+            .await;
+        let headers = resp.headers();
+
+        assert_eq!(resp.status(), Status::NoContent);
+        assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some(origin));
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Credentials"),
+            Some("true")
+        );
+        assert_eq!(headers.get_one("Access-Control-Max-Age"), Some("86400"));
+        assert!(headers.get_one("Access-Control-Allow-Methods").is_some());
+        assert_eq!(headers.get_one("Vary"), Some("Origin"));
+
+        let preflights = [
+            ("https://evil.com", "GET", "Content-Type"),
+            (origin, "PUT", "Content-Type"),
+            (origin, "GET", "X-Custom"),
+        ];
+
+        for (origin, method, headers) in preflights {
+            let resp = client
+                .options(status.as_str())
+                .header(Header::new("Origin", origin))
+                .header(Header::new("Access-Control-Request-Method", method))
+                .header(Header::new("Access-Control-Request-Headers", headers))
+                .dispatch()
+                .await;
+
+            assert_eq!(resp.status(), Status::Forbidden);
+            assert!(resp
+                .headers()
+                .get_one("Access-Control-Allow-Methods")
+                .is_none());
+        }
+
+        let resp = client
+            .get(status.as_str())
+            .header(Header::new("Origin", origin))
+            .dispatch()
+            .await;
+
+        assert_eq!(resp.status(), Status::NotFound);
+        assert_eq!(
+            resp.headers().get_one("Access-Control-Allow-Origin"),
+            Some(origin)
+        );
+
+        let resp = client
+            .get(status.as_str())
+            .header(Header::new("Origin", "https://evil.com"))
+            .dispatch()
+            .await;
+
+        assert!(resp
+            .headers()
+            .get_one("Access-Control-Allow-Origin")
+            .is_none());
+
+        let client = local_client(rendezvous.config()).await?;
+
+        let resp = client
+            .get(status.as_str())
+            .header(Header::new("Origin", origin))
+            .dispatch()
+            .await;
+
+        assert_eq!(
+            resp.headers().get_one("Access-Control-Allow-Origin"),
+            Some("*")
+        );
+        assert!(resp.headers().get_one("Vary").is_none());
+
+        Ok(())
+    }
+
+    /// Tests that the configured security headers are added to responses.
+    #[tokio::test]
+    async fn test_security_headers() -> Result<(), ThreadSafeError> {
+        use pylon_web::config::SecurityHeadersConfig;
+        use pylon_web::consts::API_PREFIX;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(PylonConfig {
+            security_headers: SecurityHeadersConfig {
+                frame_ancestors: "https://example.com".into(),
+                permissions_policy: "".into(),
+                ..Default::default()
+            },
+            ..rendezvous.config()
+        })
+        .await?;
+
+        let resp = client
+            .get(format!("{}/status/1-hello-world", API_PREFIX))
+            .dispatch()
+            .await;
+        let headers = resp.headers();
+        let csp = headers
+            .get_one("Content-Security-Policy")
+            .unwrap_or_default();
+
+        assert!(csp.starts_with("default-src 'self'"));
+        assert!(csp.ends_with("; frame-ancestors https://example.com"));
+        assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"));
+        assert_eq!(headers.get_one("Referrer-Policy"), Some("no-referrer"));
+        assert!(headers.get_one("Strict-Transport-Security").is_some());
+        assert!(headers.get_one("Permissions-Policy").is_none());
+
+        Ok(())
+    }
+
+    /// Tests that clients are rate limited per IP address, once their budget is used up.
+    #[tokio::test]
+    async fn test_rate_limit() -> Result<(), ThreadSafeError> {
+        use pylon_web::config::{RateBudget, RateLimitConfig};
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        use rocket::http::{Header, Status};
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(PylonConfig {
+            rate_limit: RateLimitConfig {
+                trusted_proxy_header: Some("X-Forwarded-For".into()),
+                receive: RateBudget {
+                    burst: 2,
+                    per_minute: 1,
+                },
+                ..Default::default()
+            },
+            ..rendezvous.config()
+        })
+        .await?;
+        let path = format!("{}/receive", API_PREFIX);
+
+        let receive = |forwarded_for: &'static str| {
+            client
+                .post(path.as_str())
+                .header(Header::new("X-Forwarded-For", forwarded_for))
+                .json(&Payload::from(("", "not-a-code")))
+                .dispatch()
+        };
+
+        // Only the address appended by the proxy counts, so spoofed prefixes don't help.
+        for forwarded_for in ["10.0.0.1", "1.2.3.4, 10.0.0.1"] {
+            assert_eq!(receive(forwarded_for).await.status(), Status::Forbidden);
+        }
+
+        let resp = receive("5.6.7.8, 10.0.0.1").await;
+
+        assert_eq!(resp.status(), Status::TooManyRequests);
+
+        let retry_after: u64 = resp
+            .headers()
+            .get_one("Retry-After")
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or_default();
+
+        assert!((1..=60).contains(&retry_after));
+
+        let body: Response<Payload> = resp.into_json().await.expect("invalid error body");
+
+        assert_eq!(body.error_code.as_deref(), Some("rate_limited"));
+        assert_eq!(receive("10.0.0.2").await.status(), Status::Forbidden);
+
+        Ok(())
+    }
+
+    /// Tests that a sender can cancel its code, and that waiting receivers are told so.
+    #[tokio::test]
+    async fn test_cancel_code() -> Result<(), ThreadSafeError> {
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        use rocket::http::Status;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = Arc::new(local_client(rendezvous.config()).await?);
+
+        let resp = client.get(format!("{}/code", API_PREFIX)).dispatch().await;
+        let body: Response<CodeInfo> = resp.into_json().await.expect("invalid code body");
+        let code = body.data.ok_or("missing code info")?.code;
+
+        let receiver = Arc::clone(&client);
+        let receive_code = code.clone();
+        let receive_handle = tokio::spawn(async move {
+            let resp = receiver
+                .post(format!("{}/receive", API_PREFIX))
+                .json(&Payload::from(("", receive_code.as_str())))
+                .dispatch()
+                .await;
+            let status = resp.status();
+            let body: Option<Response<Payload>> = resp.into_json().await;
+
+            (status, body.and_then(|body| body.error_code))
+        });
+
+        // Give the receiver some time to start waiting for the sender.
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        let resp = client
+            .delete(format!("{}/code/{}", API_PREFIX, code))
+            .dispatch()
+            .await;
+
+        assert_eq!(resp.status(), Status::Ok);
+
+        let body: Response<TransferStatus> = resp.into_json().await.expect("invalid status body");
+
+        assert_eq!(
+            body.data.map(|status| status.state),
+            Some(TransferState::Cancelled)
+        );
+        assert_eq!(
+            receive_handle.await?,
+            (Status::Gone, Some("cancelled".into()))
+        );
+        assert_eq!(
+            client
+                .delete(format!("{}/code/{}", API_PREFIX, code))
+                .dispatch()
+                .await
+                .status(),
+            Status::NotFound
+        );
+
+        // The sender is closed once its receivers are notified.
+        assert_eq!(rendezvous.moods().await, ["lonely"]);
+
+        Ok(())
+    }
+
+    /// Tests the liveness and readiness probes, with the rendezvous server up and down.
+    #[tokio::test]
+    async fn test_health() -> Result<(), ThreadSafeError> {
+        use pylon_web::core::{CheckState, HealthReport};
+        use pylon_web::{routes, Response};
+
+        use rocket::http::Status;
+
+        use rocket::uri;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(rendezvous.config()).await?;
+
+        let resp = client.get(uri!(routes::healthz)).dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+
+        let resp = client.get(uri!(routes::readyz)).dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+
+        let body: Response<HealthReport> = resp.into_json().await.expect("invalid health body");
+        let report = body.data.expect("missing health report");
+
+        assert_eq!(report.state, CheckState::Ok);
+        assert_eq!(report.checks.len(), 2);
+        assert!(report
+            .checks
+            .values()
+            .all(|check| check.state == CheckState::Ok));
+
+        // Stopping the rendezvous server makes the service unready, but still alive.
+        drop(rendezvous);
+
+        let resp = client.get(uri!(routes::readyz)).dispatch().await;
+
+        assert_eq!(resp.status(), Status::ServiceUnavailable);
+
+        let body: Response<HealthReport> = resp.into_json().await.expect("invalid health body");
+        let report = body.data.expect("missing health report");
+
+        assert_eq!(report.state, CheckState::Failed);
+        assert_eq!(report.checks["rendezvous"].state, CheckState::Failed);
+        assert!(report.checks["rendezvous"].error.is_some());
+        assert_eq!(report.checks["store"].state, CheckState::Ok);
+        assert_eq!(
+            client.get(uri!(routes::healthz)).dispatch().await.status(),
+            Status::Ok
+        );
+
+        Ok(())
+    }
+
+    /// Tests that generated codes and handled requests show up in the Prometheus metrics.
+    #[tokio::test]
+    async fn test_metrics() -> Result<(), ThreadSafeError> {
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::routes;
+
+        use rocket::http::{ContentType, Status};
+
+        use rocket::uri;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(rendezvous.config()).await?;
+
+        assert_eq!(
+            client
+                .get(format!("{}/code", API_PREFIX))
+                .dispatch()
+                .await
+                .status(),
+            Status::Ok
+        );
+        assert_eq!(
+            client
+                .get(format!("{}/status/unknown-code", API_PREFIX))
+                .dispatch()
+                .await
+                .status(),
+            Status::NotFound
+        );
+
+        let resp = client.get(uri!(routes::metrics)).dispatch().await;
+
+        assert_eq!(resp.status(), Status::Ok);
+        assert_eq!(
+            resp.content_type().map(|ct| ct.media_type().clone()),
+            Some(ContentType::Plain.media_type().clone())
+        );
+
+        let body = resp.into_string().await.unwrap_or_default();
+
+        assert!(body.contains("pylon_codes_generated_total 1"));
+        assert!(body.contains("pylon_pending_codes 1"));
+        assert!(body.contains(&format!(
+            r#"pylon_http_requests_total{{method="GET",route="{}/code?<words>",status="200"}} 1"#,
+            API_PREFIX
+        )));
+        assert!(body.contains(&format!(
+            r#"pylon_http_requests_total{{method="GET",route="{}/status/<code>",status="404"}} 1"#,
+            API_PREFIX
+        )));
+
+        Ok(())
+    }
+
+    /// Tests the high-level API endpoints' responses.
+    #[tokio::test]
+    async fn test_api_endpoints() -> Result<(), ThreadSafeError> {
+        use pylon_web::consts::API_PREFIX;
+        use pylon_web::Response;
+
+        use rocket::http::Status;
+
+        let rendezvous = LocalRendezvous::start().await?;
+        let client = local_client(rendezvous.config()).await?;
+
+        // Test `/code` endpoint and store its status and body (to retrieve the generated code).
+        let resp = client
+            .get(format!("{}/code?words=4", API_PREFIX))
+            .dispatch()
+            .await;
+        let body: Response<CodeInfo> = resp.into_json().await.expect("invalid code body");
+        let info = body.data.expect("missing code info");
+
+        assert_eq!(info.words, 4);
+        assert_eq!(info.entropy_bits, 32.0);
+        assert_eq!(info.code.split('-').count(), 5);
+
+        let resp = client.get(format!("{}/code", API_PREFIX)).dispatch().await;
+        let status = resp.status();
+        let body: Option<Response<CodeInfo>> = resp.into_json().await;
+
+        assert_eq!(status, Status::Ok);
+
+        if let Some(body) = body {
+            if let Some(CodeInfo { code, .. }) = body.data {
+                // Wrap client in an Arc, to make it shareable between threads.
+                let arc_client = Arc::new(client);
+                let code_copy = code.clone();
+                let code_status = code.clone();
+
+                // `/send` endpoint thread.
+                let client = Arc::clone(&arc_client);
+                let send_handle = tokio::spawn(async move {
+                    let payload: Payload = ("Hello world", code_copy.as_str()).into();
+                    let resp = client
+                        .post(format!("{}/send", API_PREFIX))
+                        .json(&payload)
+                        .dispatch()
+                        .await;
+
+                    assert_eq!(resp.status(), Status::Accepted);
+
+                    // Test response when payload not sent.
+                    let resp = client.post(format!("{}/send", API_PREFIX)).dispatch().await;
+
+                    assert_ne!(resp.status(), Status::Ok);
+                });
+
+                // `/receive` endpoint thread.
+                let client = Arc::clone(&arc_client);
+                let recv_handle = tokio::spawn(async move {
+                    let payload: Payload = ("", code.as_str()).into();
+                    let resp = client
+                        .post(format!("{}/receive", API_PREFIX))
+                        .json(&payload)
+                        .dispatch()
+                        .await;
+
+                    assert_eq!(resp.status(), Status::Ok);
+
+                    // Test response when payload not sent.
+                    let resp = client.post(format!("{}/send", API_PREFIX)).dispatch().await;
+
+                    assert_ne!(resp.status(), Status::Ok);
+                });
+
+                send_handle.await?;
+                recv_handle.await?;
+
+                // The transfer runs in the background, so poll `/status/<code>` until it settles.
+                let mut state = TransferState::Pending;
+
+                for _ in 0..50 {
+                    let resp = arc_client
+                        .get(format!("{}/status/{}", API_PREFIX, code_status))
+                        .dispatch()
+                        .await;
+                    let body: Option<Response<TransferStatus>> = resp.into_json().await;
+
+                    if let Some(TransferStatus { state: current, .. }) = body.and_then(|b| b.data) {
+                        state = current;
+                    }
+
+                    if state != TransferState::Pending && state != TransferState::Connected {
+                        break;
+                    }
+
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                }
+
+                assert_eq!(state, TransferState::Delivered);
             } else {
                 return Err("Code generation failed".into());
             }
@@ -16,9 +16,34 @@ const copyToClipboard = str => {
 
 function SenderForm(props) {
 	const [code, setCode] = React.useState();
+	const [expiresAt, setExpiresAt] = React.useState();
+	const [remaining, setRemaining] = React.useState();
 	const [message, setMessage] = React.useState();
 	const [inProgress, setInProgress] = React.useState();
 
+	React.useEffect(() => {
+		if (!expiresAt) {
+			setRemaining(null);
+			return;
+		}
+
+		const tick = () => {
+			let secs = Math.max(0, Math.round(expiresAt - Date.now() / 1000));
+			setRemaining(secs);
+
+			if (secs === 0) {
+				toast.error("Code expired");
+				setCode(null);
+				setExpiresAt(null);
+			}
+		};
+
+		tick();
+		const timer = setInterval(tick, 1000);
+
+		return () => clearInterval(timer);
+	}, [expiresAt]);
+
 	const getMessage = (e) => {
 		setMessage(e.target.value);
 	}
@@ -30,15 +55,16 @@ function SenderForm(props) {
 
 		await axios({
 			method: "GET",
-			url: `https://${addr}:443/code`,
+			url: `https://${addr}:443/api/v1/code`,
 			timeout: 1000 * 30,
 		}).then(resp => {
 			if (resp.status !== 200) {
 				toast.error("Failed to generate code");
 				setCode(null);
 			} else {
-				setCode(resp.data.data);
-				copyToClipboard(resp.data.data).catch(() => {
+				setCode(resp.data.data.code);
+				setExpiresAt(resp.data.data.expires_at.secs_since_epoch);
+				copyToClipboard(resp.data.data.code).catch(() => {
 					toast.error("Failed to copy code to clipboard");
 				});
 				toast.info("Code copied to clipboard");
@@ -49,14 +75,31 @@ function SenderForm(props) {
 		});
 	}
 
+	const pollStatus = async (addr, code) => {
+		for (;;) {
+			let state = await axios({
+				method: "GET",
+				url: `https://${addr}:443/api/v1/status/${encodeURIComponent(code)}`,
+				timeout: 1000 * 30,
+			}).then(resp => resp.data.data.state).catch(() => "failed");
+
+			if (state !== "pending" && state !== "connected") {
+				return state;
+			}
+
+			await new Promise(resolve => setTimeout(resolve, 1000));
+		}
+	}
+
 	const sendMessage = async () => {
 		setInProgress(true);
+		setExpiresAt(null);
 
 		let addr = "pylon-web-osl65qagha-uc.a.run.app";
 
 		await axios({
 			method: "POST",
-			url: `https://${addr}:443/send`,
+			url: `https://${addr}:443/api/v1/send`,
 			timeout: 1000 * 30,
 			headers: {
 				"Content-Type": "application/json",
@@ -65,14 +108,23 @@ function SenderForm(props) {
 				code,
 				message,
 			},
-		}).then(resp => {
-			if (resp.status !== 200) {
-				toast.error("Receiving message failed");
-			} else {
+		}).then(async resp => {
+			if (resp.status !== 202) {
+				toast.error("Sending message failed");
+				return;
+			}
+
+			let state = await pollStatus(addr, code);
+
+			if (state === "delivered") {
 				toast.success("Message sent successfully");
+			} else if (state === "expired") {
+				toast.error("Code expired before the message was received");
+			} else {
+				toast.error("Sending message failed");
 			}
 		}).catch(() => {
-			toast.error("Sending message timed out");
+			toast.error("Sending message failed");
 		})
 
 		setCode(null);
@@ -87,6 +139,9 @@ function SenderForm(props) {
 		<div className="SenderForm">
 			<h4 className="SenderForm-label">Code:</h4>
 			<div className="SenderForm-code">{code || "-"}</div>
+			{remaining !== null && remaining !== undefined &&
+				<h6 className="SenderForm-expiry">Expires in: {Math.floor(remaining / 60)}:{String(remaining % 60).padStart(2, "0")}</h6>
+			}
 			<Button text={"Generate"} onClick={genCode} disabled={code} />
 
 			{inProgress ?